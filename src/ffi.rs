@@ -789,6 +789,32 @@ pub const PT_MCH15: PixelType = PixelType(29);
 /// Identical to PT_Lab, but using the V2 old encoding
 pub const PT_LabV2: PixelType = PixelType(30);
 
+// Bit fields of `PixelFormat`, like the LCMS macros of the same names
+#[inline]
+pub const fn PREMUL_SH(m: u32) -> u32 { m << 23 }
+#[inline]
+pub const fn FLOAT_SH(a: u32) -> u32 { a << 22 }
+#[inline]
+pub const fn OPTIMIZED_SH(s: u32) -> u32 { s << 21 }
+#[inline]
+pub const fn COLORSPACE_SH(s: PixelType) -> u32 { s.0 << 16 }
+#[inline]
+pub const fn SWAPFIRST_SH(s: u32) -> u32 { s << 14 }
+#[inline]
+pub const fn FLAVOR_SH(s: u32) -> u32 { s << 13 }
+#[inline]
+pub const fn PLANAR_SH(p: u32) -> u32 { p << 12 }
+#[inline]
+pub const fn ENDIAN16_SH(e: u32) -> u32 { e << 11 }
+#[inline]
+pub const fn DOSWAP_SH(e: u32) -> u32 { e << 10 }
+#[inline]
+pub const fn EXTRA_SH(e: u32) -> u32 { e << 7 }
+#[inline]
+pub const fn CHANNELS_SH(c: u32) -> u32 { c << 3 }
+#[inline]
+pub const fn BYTES_SH(b: u32) -> u32 { b }
+
 /// Format of pixel is defined by one u32, using bit fields as follows
 ///
 ///                               2                1          0
@@ -1084,6 +1110,9 @@ fn test_pixelformat() {
 
     assert_eq!(2, PixelFormat::CMYK_HALF_FLT.bytes_per_channel());
     assert_eq!(PT_CMYK, PixelFormat::CMYK_HALF_FLT.pixel_type());
+
+    assert_eq!(PixelFormat::RGB_16_PLANAR.0, COLORSPACE_SH(PT_RGB) | PLANAR_SH(1) | CHANNELS_SH(3) | BYTES_SH(2));
+    assert_eq!(PixelFormat::CMYK_HALF_FLT.0, FLOAT_SH(1) | COLORSPACE_SH(PT_CMYK) | CHANNELS_SH(4) | BYTES_SH(2));
 }
 
 #[repr(C)]
@@ -1418,6 +1447,11 @@ pub enum Intent {
     PreserveKPlaneSaturation = 15,
 }
 
+/// Any rendering intent code, including [`Intent`]s and intents registered by plug-ins
+#[repr(transparent)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct IntentCode(pub u32);
+
 // Flags
 
 /// Inhibit 1-pixel cache
@@ -1512,6 +1546,396 @@ pub struct MHC2Type {
 #[deprecated(note = "use MHC2Type")]
 pub type cmsMHC2Type = MHC2Type;
 
+// Plug-in foundation (lcms2_plugin.h)
+
+/// Vector of 3 elements. The axes have no specific meaning.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct VEC3 {
+    pub n: [f64; 3],
+}
+
+/// 3x3 matrix, row by row
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct MAT3 {
+    pub v: [VEC3; 3],
+}
+
+/// 'acpp'
+pub const PluginMagicNumber: Signature =             0x61637070;
+
+/// 'memH'
+pub const PluginMemHandlerSig: Signature =           0x6D656D48;
+/// 'inpH'
+pub const PluginInterpolationSig: Signature =        0x696E7048;
+/// 'parH'
+pub const PluginParametricCurveSig: Signature =      0x70617248;
+/// 'frmH'
+pub const PluginFormattersSig: Signature =           0x66726D48;
+/// 'typH'
+pub const PluginTagTypeSig: Signature =              0x74797048;
+/// 'tagH'
+pub const PluginTagSig: Signature =                  0x74616748;
+/// 'intH'
+pub const PluginRenderingIntentSig: Signature =      0x696E7448;
+/// 'mpeH'
+pub const PluginMultiProcessElementSig: Signature =  0x6D706548;
+/// 'optH'
+pub const PluginOptimizationSig: Signature =         0x6F707448;
+/// 'xfmH'
+pub const PluginTransformSig: Signature =            0x7A666D48;
+/// 'mtxH'
+pub const PluginMutexSig: Signature =                0x6D747A48;
+/// 'prlH'
+pub const PluginParalellizationSig: Signature =      0x70726C48;
+
+/// Maximum number of types in a plugin array
+pub const MAX_TYPES_IN_LCMS_PLUGIN: usize = 20;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub struct PluginBase {
+    /// 'acpp' signature
+    pub Magic: Signature,
+    /// Expected version of LittleCMS
+    pub ExpectedVersion: u32,
+    /// Type of plug-in
+    pub Type: Signature,
+    /// For multiple plugin definition. NULL for end of list.
+    pub Next: *mut PluginBase,
+}
+
+pub type FreeUserDataFn = Option<unsafe extern "C" fn(ContextID: Context, Data: *mut c_void)>;
+pub type DupUserDataFn = Option<unsafe extern "C" fn(ContextID: Context, Data: *const c_void) -> *mut c_void>;
+
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+#[derive(Debug)]
+pub struct Stride {
+    pub BytesPerLineIn: u32,
+    pub BytesPerLineOut: u32,
+    pub BytesPerPlaneIn: u32,
+    pub BytesPerPlaneOut: u32,
+}
+
+pub type Transform2Fn = Option<unsafe extern "C" fn(CMMcargo: HTRANSFORM,
+                                                    InputBuffer: *const c_void,
+                                                    OutputBuffer: *mut c_void,
+                                                    PixelsPerLine: u32,
+                                                    LineCount: u32,
+                                                    Stride: *const Stride)>;
+
+/// Let's plug-in to guess the best number of workers
+pub const CMS_GUESS_MAX_WORKERS: i32 = -1;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub struct PluginParalellization {
+    pub base: PluginBase,
+    /// Number of starts to do as maximum
+    pub MaxWorkers: i32,
+    /// Reserved
+    pub WorkerFlags: u32,
+    /// callback to setup functions
+    pub SchedulerFn: Transform2Fn,
+}
+
+pub type CreateMutexFnPtrType = Option<unsafe extern "C" fn(ContextID: Context) -> *mut c_void>;
+pub type DestroyMutexFnPtrType = Option<unsafe extern "C" fn(ContextID: Context, mtx: *mut c_void)>;
+pub type LockMutexFnPtrType = Option<unsafe extern "C" fn(ContextID: Context, mtx: *mut c_void) -> Bool>;
+pub type UnlockMutexFnPtrType = Option<unsafe extern "C" fn(ContextID: Context, mtx: *mut c_void)>;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub struct PluginMutex {
+    pub base: PluginBase,
+    pub CreateMutexPtr: CreateMutexFnPtrType,
+    pub DestroyMutexPtr: DestroyMutexFnPtrType,
+    pub LockMutexPtr: LockMutexFnPtrType,
+    pub UnlockMutexPtr: UnlockMutexFnPtrType,
+}
+
+pub type Formatter16 = Option<unsafe extern "C" fn(CMMcargo: HTRANSFORM, Values: *mut u16, Buffer: *mut u8, Stride: u32) -> *mut u8>;
+pub type FormatterFloat = Option<unsafe extern "C" fn(CMMcargo: HTRANSFORM, Values: *mut f32, Buffer: *mut u8, Stride: u32) -> *mut u8>;
+
+/// This type holds a pointer to a formatter that can be either 16 bits or f32
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union Formatter {
+    pub Fmt16: Formatter16,
+    pub FmtFloat: FormatterFloat,
+}
+
+pub const CMS_PACK_FLAGS_16BITS: u32 = 0x0000;
+pub const CMS_PACK_FLAGS_FLOAT: u32 =  0x0001;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+#[repr(u32)]
+#[derive(Debug)]
+pub enum FormatterDirection {
+    Input = 0,
+    Output = 1,
+}
+
+/// `Type` is a specific pixel format, i.e. `TYPE_RGB_8`. `dwFlags` is `CMS_PACK_FLAGS_*` precision.
+pub type FormatterFactory = Option<unsafe extern "C" fn(Type: u32, Dir: FormatterDirection, dwFlags: u32) -> Formatter>;
+
+/// Formatters. This plug-in adds new handlers, replacing them if they already exist.
+///
+/// Formatters dealing with f32 (bps = 4) or double (bps = 0) types are requested via `FormatterFloat` callback.
+/// Others come across `Formatter16` callback.
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub struct PluginFormatters {
+    pub base: PluginBase,
+    pub FormattersFactory: FormatterFactory,
+}
+
+/// Tag type handler. Each type is free to return anything it wants, and it is up to the caller to
+/// know in advance what is the type contained in the tag.
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub struct TagTypeHandler {
+    /// The signature of the type. Plug-ins may use signatures that aren't in `TagTypeSignature`.
+    pub Signature: Signature,
+    /// Allocates and reads items
+    pub ReadPtr: Option<unsafe extern "C" fn(self_: *mut TagTypeHandler, io: *mut IOHANDLER, nItems: *mut u32, SizeOfTag: u32) -> *mut c_void>,
+    /// Writes n Items
+    pub WritePtr: Option<unsafe extern "C" fn(self_: *mut TagTypeHandler, io: *mut IOHANDLER, Ptr: *mut c_void, nItems: u32) -> Bool>,
+    /// Duplicate an item or array of items
+    pub DupPtr: Option<unsafe extern "C" fn(self_: *mut TagTypeHandler, Ptr: *const c_void, n: u32) -> *mut c_void>,
+    /// Free all resources
+    pub FreePtr: Option<unsafe extern "C" fn(self_: *mut TagTypeHandler, Ptr: *mut c_void)>,
+    /// Additional parameters used by the calling thread
+    pub ContextID: Context,
+    pub ICCVersion: u32,
+}
+
+/// Each plug-in implements a single type
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub struct PluginTagType {
+    pub base: PluginBase,
+    pub Handler: TagTypeHandler,
+}
+
+/// This is the tag plugin, which identifies tags. For writing, a pointer to function is provided.
+/// This function should return the desired type for this tag, given the version of profile
+/// and the data being serialized.
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub struct TagDescriptor {
+    /// If this tag needs an array, how many elements should keep
+    pub ElemCount: u32,
+    /// For reading. In how many types this tag can come (`MAX_TYPES_IN_LCMS_PLUGIN` maximum)
+    pub nSupportedTypes: u32,
+    pub SupportedTypes: [Signature; MAX_TYPES_IN_LCMS_PLUGIN],
+    /// For writing
+    pub DecideType: Option<unsafe extern "C" fn(ICCVersion: f64, Data: *const c_void) -> Signature>,
+}
+
+/// Plug-in implements a single tag
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub struct PluginTag {
+    pub base: PluginBase,
+    /// Plug-ins may use signatures that aren't in `TagSignature`
+    pub Signature: Signature,
+    pub Descriptor: TagDescriptor,
+}
+
+/// Custom intents. This function should join all profiles specified in the array in
+/// a single LUT. Any custom intent in the chain redirects to custom function. If more than
+/// one custom intent is found, the one located first is invoked. Usually users should use only one
+/// custom intent, so mixing custom intents in same multiprofile transform is not supported.
+pub type IntentFn = Option<unsafe extern "C" fn(ContextID: Context,
+                                                nProfiles: u32,
+                                                Intents: *mut u32,
+                                                hProfiles: *mut HPROFILE,
+                                                BPC: *mut Bool,
+                                                AdaptationStates: *mut f64,
+                                                dwFlags: u32)
+                                                -> *mut Pipeline>;
+
+/// Each plug-in defines a single intent number.
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub struct PluginRenderingIntent {
+    pub base: PluginBase,
+    /// Plug-ins may use codes that aren't in `Intent`
+    pub Intent: u32,
+    pub Link: IntentFn,
+    pub Description: [c_char; 256],
+}
+
+// Data kept in "Element" member of cmsStage, returned by `cmsStageData`
+
+/// Data of `CurveSetElemType` stages
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct StageToneCurvesData {
+    pub nCurves: u32,
+    pub TheCurves: *mut *mut ToneCurve,
+}
+
+/// Data of `MatrixElemType` stages
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct StageMatrixData {
+    /// floating point for the matrix
+    pub Double: *mut f64,
+    /// The offset
+    pub Offset: *mut f64,
+}
+
+/// Can have only one of both representations at same time
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union StageCLutTable {
+    /// Points to the table 16 bits table
+    pub T: *mut u16,
+    /// Points to the cmsFloat32Number table
+    pub TFloat: *mut f32,
+}
+
+/// Data of `CLutElemType` stages
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct StageCLutData {
+    pub Tab: StageCLutTable,
+    pub Params: *mut InterpParams,
+    pub nEntries: u32,
+    pub HasFloatValues: Bool,
+}
+
+/// Optimization. Using this plug-in, additional optimization strategies may be implemented.
+/// The function should return TRUE if any optimization is done on the LUT, this terminates
+/// the optimization search. Or FALSE if it is unable to optimize and want to give a chance
+/// to the rest of optimizers.
+pub type OPToptimizeFn = Option<unsafe extern "C" fn(Lut: *mut *mut Pipeline, Intent: u32, InputFormat: *mut u32, OutputFormat: *mut u32, dwFlags: *mut u32) -> Bool>;
+
+/// Evaluates a stage (always in floating point)
+pub type StageEvalFn = Option<unsafe extern "C" fn(In: *const f32, Out: *mut f32, mpe: *const Stage)>;
+/// Duplicates the private data of a stage
+pub type StageDupElemFn = Option<unsafe extern "C" fn(mpe: *mut Stage) -> *mut c_void>;
+/// Frees the private data of a stage
+pub type StageFreeElemFn = Option<unsafe extern "C" fn(mpe: *mut Stage)>;
+
+/// Pipeline Evaluator (in 16 bits)
+pub type PipelineEval16Fn = Option<unsafe extern "C" fn(In: *const u16, Out: *mut u16, Data: *const c_void)>;
+/// Pipeline Evaluator (in floating point)
+pub type PipelineEvalFloatFn = Option<unsafe extern "C" fn(In: *const f32, Out: *mut f32, Data: *const c_void)>;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub struct PluginOptimization {
+    pub base: PluginBase,
+    /// Optimize entry point
+    pub OptimizePtr: OPToptimizeFn,
+}
+
+/// Legacy function, handles just ONE scanline. `Stride` is in bytes to the next plane in planar formats.
+pub type TransformFn = Option<unsafe extern "C" fn(CMMcargo: HTRANSFORM, InputBuffer: *const c_void, OutputBuffer: *mut c_void, Size: u32, Stride: u32)>;
+
+pub type TransformFactory = Option<unsafe extern "C" fn(xform: *mut TransformFn,
+                                                        UserData: *mut *mut c_void,
+                                                        FreePrivateDataFn: *mut FreeUserDataFn,
+                                                        Lut: *mut *mut Pipeline,
+                                                        InputFormat: *mut u32,
+                                                        OutputFormat: *mut u32,
+                                                        dwFlags: *mut u32)
+                                                        -> Bool>;
+
+pub type Transform2Factory = Option<unsafe extern "C" fn(xform: *mut Transform2Fn,
+                                                         UserData: *mut *mut c_void,
+                                                         FreePrivateDataFn: *mut FreeUserDataFn,
+                                                         Lut: *mut *mut Pipeline,
+                                                         InputFormat: *mut u32,
+                                                         OutputFormat: *mut u32,
+                                                         dwFlags: *mut u32)
+                                                         -> Bool>;
+
+/// Transform entry point. `legacy_xform` is used when `ExpectedVersion` is below 2080.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union PluginTransformFactories {
+    pub legacy_xform: TransformFactory,
+    pub xform: Transform2Factory,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct PluginTransform {
+    pub base: PluginBase,
+    pub factories: PluginTransformFactories,
+}
+
+/// Allow change buffer format. Transform plug-ins can set it if they support `cmsChangeBuffersFormat`.
+pub const FLAGS_CAN_CHANGE_FORMATTER: u32 = 0x02000000;
+
+/// 16 bits forward interpolation. Precision-limited, and supposed to be quite fast.
+pub type InterpFn16 = Option<unsafe extern "C" fn(Input: *const u16, Output: *mut u16, p: *const InterpParams)>;
+/// Floating point forward interpolation. Full precision, not time critical.
+pub type InterpFnFloat = Option<unsafe extern "C" fn(Input: *const f32, Output: *mut f32, p: *const InterpParams)>;
+
+/// Interpolator that can be either 16 bits or float
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union InterpFunction {
+    pub Lerp16: InterpFn16,
+    pub LerpFloat: InterpFnFloat,
+}
+
+/// The default
+pub const CMS_LERP_FLAGS_16BITS: u32 = 0x0000;
+/// Requires different implementation
+pub const CMS_LERP_FLAGS_FLOAT: u32 = 0x0001;
+/// Hint only
+pub const CMS_LERP_FLAGS_TRILINEAR: u32 = 0x0100;
+
+pub const MAX_INPUT_DIMENSIONS: usize = 15;
+
+/// Used on all interpolations. Supplied by lcms2 when calling the interpolation function.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct InterpParams {
+    pub ContextID: Context,
+    /// Keep original flags
+    pub dwFlags: u32,
+    /// != 1 only in 3D interpolation
+    pub nInputs: u32,
+    /// != 1 only in 3D interpolation
+    pub nOutputs: u32,
+    pub nSamples: [u32; MAX_INPUT_DIMENSIONS],
+    /// Domain = nSamples - 1
+    pub Domain: [u32; MAX_INPUT_DIMENSIONS],
+    /// Number of nodes premultiplied for each dimension
+    pub opta: [u32; MAX_INPUT_DIMENSIONS],
+    /// Points to the actual interpolation table
+    pub Table: *const c_void,
+    pub Interpolation: InterpFunction,
+}
+
+pub type InterpFnFactory = Option<unsafe extern "C" fn(nInputChannels: u32, nOutputChannels: u32, dwFlags: u32) -> InterpFunction>;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct PluginInterpolation {
+    pub base: PluginBase,
+    pub InterpolatorsFactory: InterpFnFactory,
+}
+
 extern "C" {
     pub fn cmsGetEncodedCMMversion() -> c_int;
     pub fn cmsstrcasecmp(s1: *const c_char, s2: *const c_char) -> c_int;
@@ -1552,7 +1976,8 @@ extern "C" {
     pub fn cmsCIECAM02Done(hModel: HANDLE);
     pub fn cmsCIECAM02Forward(hModel: HANDLE, pIn: *const CIEXYZ, pOut: *mut JCh);
     pub fn cmsCIECAM02Reverse(hModel: HANDLE, pIn: *const JCh, pOut: *mut CIEXYZ);
-    pub fn cmsGetToneCurveSegment(n: u32, t: &ToneCurve) -> &CurveSegment;
+    /// Null if `n` is past the last segment
+    pub fn cmsGetToneCurveSegment(n: i32, t: *const ToneCurve) -> *const CurveSegment;
     pub fn cmsGetStageContextID(mpe: *const Stage) -> Context;
     pub fn cmsBuildSegmentedToneCurve(ContextID: Context, nSegments: u32, Segments: *const CurveSegment) -> *mut ToneCurve;
     pub fn cmsBuildParametricToneCurve(ContextID: Context, Type: i32, Params: *const f64) -> *mut ToneCurve;
@@ -1857,3 +2282,85 @@ extern "C" {
     pub fn cmsDesaturateLab(Lab: *mut CIELab, amax: f64, amin: f64, bmax: f64, bmin: f64) -> Bool;
     pub fn cmsDetectRGBProfileGamma(hProfile: HPROFILE, threshold: f64) -> f64;
 }
+
+// Functions from lcms2_plugin.h
+extern "C" {
+    pub fn _cmsVEC3init(r: *mut VEC3, x: f64, y: f64, z: f64);
+    pub fn _cmsVEC3minus(r: *mut VEC3, a: *const VEC3, b: *const VEC3);
+    pub fn _cmsVEC3cross(r: *mut VEC3, u: *const VEC3, v: *const VEC3);
+    pub fn _cmsVEC3dot(u: *const VEC3, v: *const VEC3) -> f64;
+    pub fn _cmsVEC3length(a: *const VEC3) -> f64;
+    pub fn _cmsVEC3distance(a: *const VEC3, b: *const VEC3) -> f64;
+    pub fn _cmsMAT3identity(a: *mut MAT3);
+    pub fn _cmsMAT3isIdentity(a: *const MAT3) -> Bool;
+    pub fn _cmsMAT3per(r: *mut MAT3, a: *const MAT3, b: *const MAT3);
+    pub fn _cmsMAT3inverse(a: *const MAT3, b: *mut MAT3) -> Bool;
+    /// Solves `a` × `x` = `b`
+    pub fn _cmsMAT3solve(x: *mut VEC3, a: *mut MAT3, b: *mut VEC3) -> Bool;
+    pub fn _cmsMAT3eval(r: *mut VEC3, a: *const MAT3, v: *const VEC3);
+
+    pub fn _cmsMalloc(ContextID: Context, size: u32) -> *mut c_void;
+    pub fn _cmsMallocZero(ContextID: Context, size: u32) -> *mut c_void;
+    pub fn _cmsCalloc(ContextID: Context, num: u32, size: u32) -> *mut c_void;
+    pub fn _cmsRealloc(ContextID: Context, Ptr: *mut c_void, NewSize: u32) -> *mut c_void;
+    pub fn _cmsFree(ContextID: Context, Ptr: *mut c_void);
+    pub fn _cmsDupMem(ContextID: Context, Org: *const c_void, size: u32) -> *mut c_void;
+    pub fn _cmsReadUInt8Number(io: *mut IOHANDLER, n: *mut u8) -> Bool;
+    pub fn _cmsReadUInt16Number(io: *mut IOHANDLER, n: *mut u16) -> Bool;
+    pub fn _cmsReadUInt32Number(io: *mut IOHANDLER, n: *mut u32) -> Bool;
+    pub fn _cmsReadFloat32Number(io: *mut IOHANDLER, n: *mut f32) -> Bool;
+    pub fn _cmsReadUInt64Number(io: *mut IOHANDLER, n: *mut u64) -> Bool;
+    pub fn _cmsRead15Fixed16Number(io: *mut IOHANDLER, n: *mut f64) -> Bool;
+    pub fn _cmsReadXYZNumber(io: *mut IOHANDLER, XYZ: *mut CIEXYZ) -> Bool;
+    pub fn _cmsReadUInt16Array(io: *mut IOHANDLER, n: u32, Array: *mut u16) -> Bool;
+    pub fn _cmsWriteUInt8Number(io: *mut IOHANDLER, n: u8) -> Bool;
+    pub fn _cmsWriteUInt16Number(io: *mut IOHANDLER, n: u16) -> Bool;
+    pub fn _cmsWriteUInt32Number(io: *mut IOHANDLER, n: u32) -> Bool;
+    pub fn _cmsWriteFloat32Number(io: *mut IOHANDLER, n: f32) -> Bool;
+    pub fn _cmsWriteUInt64Number(io: *mut IOHANDLER, n: *const u64) -> Bool;
+    pub fn _cmsWrite15Fixed16Number(io: *mut IOHANDLER, n: f64) -> Bool;
+    pub fn _cmsWriteXYZNumber(io: *mut IOHANDLER, XYZ: *const CIEXYZ) -> Bool;
+    pub fn _cmsWriteUInt16Array(io: *mut IOHANDLER, n: u32, Array: *const u16) -> Bool;
+    /// Returns a raw signature, because it may be a type from a plug-in
+    pub fn _cmsReadTypeBase(io: *mut IOHANDLER) -> Signature;
+    pub fn _cmsWriteTypeBase(io: *mut IOHANDLER, sig: Signature) -> Bool;
+    pub fn _cmsReadAlignment(io: *mut IOHANDLER) -> Bool;
+    pub fn _cmsWriteAlignment(io: *mut IOHANDLER) -> Bool;
+    pub fn _cmsIOPrintf(io: *mut IOHANDLER, frm: *const c_char, ...) -> Bool;
+    pub fn _cms8Fixed8toDouble(fixed8: u16) -> f64;
+    pub fn _cmsDoubleTo8Fixed8(val: f64) -> u16;
+    pub fn _cms15Fixed16toDouble(fix32: S15Fixed16Number) -> f64;
+    pub fn _cmsDoubleTo15Fixed16(v: f64) -> S15Fixed16Number;
+    pub fn _cmsEncodeDateTimeNumber(Dest: *mut DateTimeNumber, Source: *const tm);
+    pub fn _cmsDecodeDateTimeNumber(Source: *const DateTimeNumber, Dest: *mut tm);
+    /// This function allocates a generic MPE. `Type` isn't a `StageSignature`, since plug-ins define their own.
+    pub fn _cmsStageAllocPlaceholder(ContextID: Context, Type: Signature, InputChannels: u32, OutputChannels: u32,
+                                     EvalPtr: StageEvalFn, DupElemPtr: StageDupElemFn, FreePtr: StageFreeElemFn, Data: *mut c_void) -> *mut Stage;
+    /// This function may be used to set the optional evaluator and a block of private data. If private data is being used, an optional
+    /// duplicator and free functions should also be specified in order to duplicate the LUT construct. Use NULL to inhibit such functionality.
+    pub fn _cmsPipelineSetOptimizationParameters(Lut: *mut Pipeline, Eval16: PipelineEval16Fn, PrivateData: *mut c_void, FreePrivateDataFn: FreeUserDataFn, DupPrivateDataFn: DupUserDataFn);
+    /// The default ICC intents (perceptual, saturation, rel.col and abs.col)
+    pub fn _cmsDefaultICCintents(ContextID: Context,
+                                 nProfiles: u32,
+                                 Intents: *mut u32,
+                                 hProfiles: *mut HPROFILE,
+                                 BPC: *mut Bool,
+                                 AdaptationStates: *mut f64,
+                                 dwFlags: u32)
+                                 -> *mut Pipeline;
+    /// Retrieve user data as specified by the factory
+    pub fn _cmsSetTransformUserData(CMMcargo: HTRANSFORM, ptr: *mut c_void, FreePrivateDataFn: FreeUserDataFn);
+    pub fn _cmsGetTransformUserData(CMMcargo: HTRANSFORM) -> *mut c_void;
+    /// Retrieve formatters
+    pub fn _cmsGetTransformFormatters16(CMMcargo: HTRANSFORM, FromInput: *mut Formatter16, ToOutput: *mut Formatter16);
+    pub fn _cmsGetTransformFormattersFloat(CMMcargo: HTRANSFORM, FromInput: *mut FormatterFloat, ToOutput: *mut FormatterFloat);
+    /// Retrieve original flags
+    pub fn _cmsGetTransformFlags(CMMcargo: HTRANSFORM) -> u32;
+    pub fn _cmsGetTransformWorker(CMMcargo: HTRANSFORM) -> Transform2Fn;
+    pub fn _cmsGetTransformMaxWorkers(CMMcargo: HTRANSFORM) -> i32;
+    pub fn _cmsGetTransformWorkerFlags(CMMcargo: HTRANSFORM) -> u32;
+    pub fn _cmsCreateMutex(ContextID: Context) -> *mut c_void;
+    pub fn _cmsDestroyMutex(ContextID: Context, mtx: *mut c_void);
+    pub fn _cmsLockMutex(ContextID: Context, mtx: *mut c_void) -> Bool;
+    pub fn _cmsUnlockMutex(ContextID: Context, mtx: *mut c_void);
+}
@@ -5,6 +5,11 @@
 #![doc(html_root_url = "https://docs.rs/lcms2-sys")]
 
 pub mod ffi;
+pub mod lut3d;
+pub mod pipeline;
+pub mod plugin;
+pub mod tone_curve;
+pub mod transform;
 pub use crate::ffi::*;
 use std::mem::MaybeUninit;
 
@@ -65,6 +70,63 @@ impl From<CIELCh> for CIELab {
     }
 }
 
+impl From<Intent> for u32 {
+    #[inline]
+    fn from(intent: Intent) -> Self {
+        intent as u32
+    }
+}
+
+impl From<Intent> for IntentCode {
+    #[inline]
+    fn from(intent: Intent) -> Self {
+        Self(intent as u32)
+    }
+}
+
+impl From<u32> for IntentCode {
+    #[inline]
+    fn from(code: u32) -> Self {
+        Self(code)
+    }
+}
+
+impl From<IntentCode> for u32 {
+    #[inline]
+    fn from(code: IntentCode) -> Self {
+        code.0
+    }
+}
+
+impl IntentCode {
+    /// `None` for intents registered by plug-ins
+    #[must_use]
+    pub fn intent(self) -> Option<Intent> {
+        Intent::try_from(self.0).ok()
+    }
+}
+
+/// Intent codes registered by plug-ins (see `cmsGetSupportedIntentsTHR`) are returned as `Err`
+impl TryFrom<u32> for Intent {
+    type Error = u32;
+
+    fn try_from(code: u32) -> Result<Self, u32> {
+        Ok(match code {
+            0 => Intent::Perceptual,
+            1 => Intent::RelativeColorimetric,
+            2 => Intent::Saturation,
+            3 => Intent::AbsoluteColorimetric,
+            10 => Intent::PreserveKOnlyPerceptual,
+            11 => Intent::PreserveKOnlyRelativeColorimetric,
+            12 => Intent::PreserveKOnlySaturation,
+            13 => Intent::PreserveKPlanePerceptual,
+            14 => Intent::PreserveKPlaneRelativeColorimetric,
+            15 => Intent::PreserveKPlaneSaturation,
+            other => return Err(other),
+        })
+    }
+}
+
 #[test]
 fn it_works() {
     unsafe {
@@ -0,0 +1,564 @@
+//! RGB 3D LUTs, and their `.cube` (Resolve/Adobe), Autodesk `.3dl` and ACES CLF files.
+//!
+//! A [`Lut3d`] can be sampled from a pipeline or a transform, written to any of the formats,
+//! and turned back into a pipeline or a device link profile.
+
+use crate::ffi::*;
+use crate::pipeline::{Lut, OwnedStage, StageKind};
+use crate::tone_curve::Curve;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::ptr;
+
+/// Sampled RGB→RGB LUT with an optional 1D shaper
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lut3d {
+    pub title: String,
+    /// Input range of each channel. Inputs are mapped from it to 0..=1 before the shaper.
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    /// 1D LUT applied before the 3D one, with at least 2 samples evenly spaced over the domain
+    pub shaper: Option<Vec<[f32; 3]>>,
+    /// Number of grid points along each axis. At least 2.
+    pub size: usize,
+    /// `size`³ nodes. Red varies slowest and blue fastest, like in LCMS CLUTs.
+    pub table: Vec<[f32; 3]>,
+}
+
+impl Lut3d {
+    /// Calls `f` with every grid point
+    #[must_use]
+    pub fn sample(size: usize, mut f: impl FnMut([f32; 3]) -> [f32; 3]) -> Self {
+        let step = 1. / (size.max(2) - 1) as f32;
+        let mut table = Vec::with_capacity(size * size * size);
+        for r in 0..size {
+            for g in 0..size {
+                for b in 0..size {
+                    table.push(f([r as f32 * step, g as f32 * step, b as f32 * step]));
+                }
+            }
+        }
+        Self {
+            title: String::new(),
+            domain_min: [0.; 3],
+            domain_max: [1.; 3],
+            shaper: None,
+            size,
+            table,
+        }
+    }
+
+    /// Samples a 3→3 pipeline on a `size`³ grid.
+    ///
+    /// With `shaper_size`, if the pipeline starts with tone curves, they're exported as a 1D shaper
+    /// of that size, and only the rest of the pipeline is sampled on the grid.
+    #[must_use]
+    pub fn from_pipeline(lut: &Lut, size: usize, shaper_size: Option<usize>) -> Option<Self> {
+        if lut.inputs() != 3 || lut.outputs() != 3 || size < 2 {
+            return None;
+        }
+        let first = lut.stages().next();
+        if let (Some(shaper_size), Some(first)) = (shaper_size, first) {
+            if first.kind() == StageKind::CurveSet && shaper_size >= 2 {
+                let curves = first.curves()?;
+                let mut rest = Lut::new(3, 3)?;
+                for stage in lut.stages().skip(1) {
+                    rest.push(stage.to_owned()?).ok()?;
+                }
+                let step = 1. / (shaper_size - 1) as f32;
+                let shaper = (0..shaper_size).map(|i| {
+                    let x = i as f32 * step;
+                    [curves[0].eval_f32(x), curves[1].eval_f32(x), curves[2].eval_f32(x)]
+                }).collect();
+                let mut sampled = Self::from_pipeline(&rest, size, None)?;
+                sampled.shaper = Some(shaper);
+                return Some(sampled);
+            }
+        }
+        Some(Self::sample(size, |rgb| {
+            let mut out = [0.; 3];
+            lut.eval_f32(&rgb, &mut out);
+            out
+        }))
+    }
+
+    /// Samples a transform on a `size`³ grid.
+    ///
+    /// Both of its formats must be chunky RGB without extra channels: 8 or 16-bit integers, or 32 or 64-bit floats.
+    ///
+    /// # Safety
+    ///
+    /// The transform must be valid.
+    #[must_use]
+    pub unsafe fn from_transform(xform: HTRANSFORM, size: usize) -> Option<Self> {
+        let input = cmsGetTransformInputFormat(xform);
+        let output = cmsGetTransformOutputFormat(xform);
+        if size < 2 || !is_plain_rgb(input) || !is_plain_rgb(output) {
+            return None;
+        }
+        let mut sampled = Self::sample(size, |rgb| rgb);
+        let mut in_buf = Vec::with_capacity(sampled.table.len() * input.bytes_per_pixel());
+        for v in sampled.table.iter().flatten() {
+            encode(input, *v, &mut in_buf);
+        }
+        let mut out_buf = vec![0u8; sampled.table.len() * output.bytes_per_pixel()];
+        cmsDoTransform(xform, in_buf.as_ptr().cast(), out_buf.as_mut_ptr().cast(), sampled.table.len() as u32);
+        let channel = output.bytes_per_channel();
+        for (node, pixel) in sampled.table.iter_mut().zip(out_buf.chunks_exact(channel * 3)) {
+            for (v, bytes) in node.iter_mut().zip(pixel.chunks_exact(channel)) {
+                *v = decode(output, bytes);
+            }
+        }
+        Some(sampled)
+    }
+
+    /// Pipeline made of a domain-scaling matrix (if needed), shaper curves (if any), and a float CLUT
+    #[must_use]
+    pub fn to_pipeline(&self) -> Option<Lut> {
+        unsafe { self.to_pipeline_thr(ptr::null_mut()) }
+    }
+
+    /// Same as [`Lut3d::to_pipeline`], allocated in the `context`
+    ///
+    /// # Safety
+    ///
+    /// `context` must be valid or null.
+    #[must_use]
+    pub unsafe fn to_pipeline_thr(&self, context: Context) -> Option<Lut> {
+        if !self.is_valid() {
+            return None;
+        }
+        let mut lut = Lut::new_thr(context, 3, 3)?;
+        if !self.has_default_domain() {
+            let scale = [0, 1, 2].map(|i| 1. / f64::from(self.domain_max[i] - self.domain_min[i]));
+            let matrix = [scale[0], 0., 0., 0., scale[1], 0., 0., 0., scale[2]];
+            let offset = [0, 1, 2].map(|i| -f64::from(self.domain_min[i]) * scale[i]);
+            lut.push(OwnedStage::from_ptr(cmsStageAllocMatrix(context, 3, 3, matrix.as_ptr(), offset.as_ptr()))?).ok()?;
+        }
+        if let Some(shaper) = &self.shaper {
+            let curves = [0, 1, 2].map(|c| {
+                let values: Vec<_> = shaper.iter().map(|v| v[c]).collect();
+                Curve::tabulated_f32_thr(context, &values)
+            });
+            let [r, g, b] = curves;
+            let (r, g, b) = (r?, g?, b?);
+            let curves = [r.as_ptr() as *const ToneCurve, g.as_ptr(), b.as_ptr()];
+            lut.push(OwnedStage::from_ptr(cmsStageAllocToneCurves(context, 3, curves.as_ptr()))?).ok()?;
+        }
+        let table: Vec<_> = self.table.iter().flatten().copied().collect();
+        let grid = [self.size as u32; 3];
+        lut.push(OwnedStage::from_ptr(cmsStageAllocCLutFloatGranular(context, grid.as_ptr(), 3, 3, table.as_ptr()))?).ok()?;
+        Some(lut)
+    }
+
+    /// RGB device link profile with the LUT in its `AToB0Tag`, like `cmsCreateDeviceLinkFromCubeFile` makes.
+    ///
+    /// The caller has to close the profile. Null on failure.
+    #[must_use]
+    pub fn to_device_link(&self) -> HPROFILE {
+        unsafe { self.to_device_link_thr(ptr::null_mut()) }
+    }
+
+    /// Same as [`Lut3d::to_device_link`], like `cmsCreateDeviceLinkFromCubeFileTHR`
+    ///
+    /// # Safety
+    ///
+    /// `context` must be valid or null.
+    #[must_use]
+    pub unsafe fn to_device_link_thr(&self, context: Context) -> HPROFILE {
+        let lut = match self.to_pipeline_thr(context) {
+            Some(lut) => lut,
+            None => return ptr::null_mut(),
+        };
+        let mut title = self.title.replace('\0', "").into_bytes();
+        title.push(0);
+        let profile = cmsCreateProfilePlaceholder(context);
+        if profile.is_null() {
+            return profile;
+        }
+        cmsSetProfileVersion(profile, 4.4);
+        cmsSetDeviceClass(profile, ProfileClassSignature::LinkClass);
+        cmsSetColorSpace(profile, ColorSpaceSignature::RgbData);
+        cmsSetPCS(profile, ColorSpaceSignature::RgbData);
+        cmsSetHeaderRenderingIntent(profile, Intent::Perceptual);
+
+        let description = cmsMLUalloc(context, 1);
+        let no_code = b"\0\0\0".as_ptr().cast();
+        let ok = !description.is_null()
+            && cmsMLUsetUTF8(description, no_code, no_code, title.as_ptr().cast()) != 0
+            && cmsWriteTag(profile, TagSignature::ProfileDescriptionTag, description.cast()) != 0
+            && cmsWriteTag(profile, TagSignature::AToB0Tag, lut.as_ptr().cast()) != 0;
+        if !description.is_null() {
+            cmsMLUfree(description);
+        }
+        if !ok {
+            cmsCloseProfile(profile);
+            return ptr::null_mut();
+        }
+        profile
+    }
+
+    /// Reads a whole `.cube` file, e.g. from a database blob, without needing a temporary file.
+    ///
+    /// Syntax errors have kind `InvalidData` and wrap a [`CubeError`].
+    pub fn read_cube(mut r: impl Read) -> io::Result<Self> {
+        let mut data = Vec::new();
+        r.read_to_end(&mut data)?;
+        Self::from_cube(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Parses a `.cube` file in the Resolve/Adobe dialect. Values from `DOMAIN_MIN`/`DOMAIN_MAX` are kept as the domain.
+    ///
+    /// A file with only `LUT_1D_SIZE` becomes the shaper, followed by an identity 2×2×2 table.
+    pub fn from_cube(data: &[u8]) -> Result<Self, CubeError> {
+        let text = String::from_utf8_lossy(data);
+        let mut lut = Self::sample(0, |rgb| rgb);
+        let mut shaper_size = 0;
+        let mut rows = Vec::new();
+        let mut line_no = 0;
+        for (i, line) in text.lines().enumerate() {
+            line_no = i + 1;
+            let err = |message: &str| CubeError { line: line_no, message: message.into() };
+            let line = line.trim();
+            if let Some(title) = line.strip_prefix("TITLE").filter(|t| t.is_empty() || t.starts_with(char::is_whitespace)) {
+                let title = title.trim();
+                lut.title = title.strip_prefix('"').and_then(|t| t.strip_suffix('"'))
+                    .ok_or_else(|| err("title must be in quotes"))?.into();
+                continue;
+            }
+            let line = line.split('#').next().unwrap_or_default();
+            let mut tokens = line.split_whitespace();
+            let keyword = match tokens.next() {
+                Some(k) => k,
+                None => continue,
+            };
+            if keyword.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '.' || c == '+') {
+                let row = parse_floats(line.split_whitespace()).ok_or_else(|| err("expected 3 numbers"))?;
+                rows.push(row);
+                continue;
+            }
+            if !rows.is_empty() {
+                return Err(err("keyword after table data"));
+            }
+            match keyword {
+                "DOMAIN_MIN" => lut.domain_min = parse_floats(tokens).ok_or_else(|| err("expected 3 numbers"))?,
+                "DOMAIN_MAX" => lut.domain_max = parse_floats(tokens).ok_or_else(|| err("expected 3 numbers"))?,
+                "LUT_1D_SIZE" => {
+                    shaper_size = parse_size(tokens).filter(|s| (2..=65536).contains(s))
+                        .ok_or_else(|| err("LUT_1D_SIZE must be between 2 and 65536"))?;
+                },
+                // Only the default range is supported, like in LCMS
+                "LUT_1D_INPUT_RANGE" | "LUT_3D_INPUT_RANGE" => {
+                    let mut range = [0.; 2];
+                    for v in &mut range {
+                        *v = tokens.next().and_then(|t| t.parse::<f32>().ok()).ok_or_else(|| err("expected 2 numbers"))?;
+                    }
+                    if range != [0., 1.] || tokens.next().is_some() {
+                        return Err(err("only 0 1 input range is supported"));
+                    }
+                },
+                "LUT_3D_SIZE" => {
+                    lut.size = parse_size(tokens).filter(|s| (2..=256).contains(s))
+                        .ok_or_else(|| err("LUT_3D_SIZE must be between 2 and 256"))?;
+                },
+                _ => return Err(err("unsupported keyword")),
+            }
+        }
+        let err = |message: String| CubeError { line: line_no, message };
+        if lut.size == 0 && shaper_size == 0 {
+            return Err(err("LUT_3D_SIZE or LUT_1D_SIZE is missing".into()));
+        }
+        let nodes = lut.size * lut.size * lut.size;
+        if rows.len() != shaper_size + nodes {
+            return Err(err(format!("expected {} rows of data, found {}", shaper_size + nodes, rows.len())));
+        }
+        if !lut.has_valid_domain() {
+            return Err(err("DOMAIN_MAX must be greater than DOMAIN_MIN".into()));
+        }
+        let table = rows.split_off(shaper_size);
+        if shaper_size > 0 {
+            lut.shaper = Some(rows);
+        }
+        if lut.size == 0 {
+            lut.size = 2;
+            lut.table = Self::sample(2, |rgb| rgb).table;
+            return Ok(lut);
+        }
+        // Red varies fastest in the file
+        let n = lut.size;
+        lut.table = vec![[0.; 3]; nodes];
+        for (i, row) in table.into_iter().enumerate() {
+            let (r, g, b) = (i % n, i / n % n, i / (n * n));
+            lut.table[(r * n + g) * n + b] = row;
+        }
+        Ok(lut)
+    }
+
+    /// Writes a `.cube` file, with a `LUT_1D_SIZE` shaper if there is one
+    pub fn write_cube(&self, mut w: impl Write) -> io::Result<()> {
+        self.check()?;
+        if !self.title.is_empty() {
+            writeln!(w, "TITLE \"{}\"", self.title.replace(['"', '\n', '\r'], "'"))?;
+        }
+        if !self.has_default_domain() {
+            writeln!(w, "DOMAIN_MIN {} {} {}", self.domain_min[0], self.domain_min[1], self.domain_min[2])?;
+            writeln!(w, "DOMAIN_MAX {} {} {}", self.domain_max[0], self.domain_max[1], self.domain_max[2])?;
+        }
+        if let Some(shaper) = &self.shaper {
+            writeln!(w, "LUT_1D_SIZE {}", shaper.len())?;
+        }
+        writeln!(w, "LUT_3D_SIZE {}", self.size)?;
+        for [r, g, b] in self.shaper.iter().flatten() {
+            writeln!(w, "{r} {g} {b}")?;
+        }
+        let n = self.size;
+        for b in 0..n {
+            for g in 0..n {
+                for r in 0..n {
+                    let [vr, vg, vb] = self.table[(r * n + g) * n + b];
+                    writeln!(w, "{vr} {vg} {vb}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes an Autodesk `.3dl` file with 10-bit input and 12-bit output.
+    ///
+    /// The format has no shaper or domain, so they're baked into the 3D table.
+    pub fn write_3dl(&self, mut w: impl Write) -> io::Result<()> {
+        self.check()?;
+        let baked;
+        let lut = if self.shaper.is_some() || !self.has_default_domain() {
+            let pipeline = self.to_pipeline().ok_or_else(|| invalid("can't bake the shaper"))?;
+            baked = Self::sample(self.size, |rgb| {
+                let mut out = [0.; 3];
+                pipeline.eval_f32(&rgb, &mut out);
+                out
+            });
+            &baked
+        } else {
+            self
+        };
+        let n = lut.size;
+        let mesh: Vec<_> = (0..n).map(|i| (i * 1023 / (n - 1)).to_string()).collect();
+        writeln!(w, "{}", mesh.join(" "))?;
+        for node in &lut.table {
+            let [r, g, b] = node.map(|v| (v.clamp(0., 1.) * 4095.).round() as u16);
+            writeln!(w, "{r} {g} {b}")?;
+        }
+        Ok(())
+    }
+
+    /// Writes an ACES Common LUT Format (CLF 3.0) `ProcessList` with 32-bit float nodes
+    pub fn write_clf(&self, mut w: impl Write) -> io::Result<()> {
+        self.check()?;
+        writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(w, r#"<ProcessList id="lcms2" compCLFversion="3.0">"#)?;
+        if !self.title.is_empty() {
+            writeln!(w, "  <Description>{}</Description>", escape_xml(&self.title))?;
+        }
+        if !self.has_default_domain() {
+            writeln!(w, r#"  <Matrix inBitDepth="32f" outBitDepth="32f">"#)?;
+            writeln!(w, r#"    <Array dim="3 4">"#)?;
+            for c in 0..3 {
+                let scale = 1. / (self.domain_max[c] - self.domain_min[c]);
+                let mut row = [0.; 4];
+                row[c] = scale;
+                row[3] = -self.domain_min[c] * scale;
+                writeln!(w, "      {} {} {} {}", row[0], row[1], row[2], row[3])?;
+            }
+            writeln!(w, "    </Array>\n  </Matrix>")?;
+        }
+        if let Some(shaper) = &self.shaper {
+            writeln!(w, r#"  <LUT1D inBitDepth="32f" outBitDepth="32f">"#)?;
+            writeln!(w, r#"    <Array dim="{} 3">"#, shaper.len())?;
+            for [r, g, b] in shaper {
+                writeln!(w, "      {r} {g} {b}")?;
+            }
+            writeln!(w, "    </Array>\n  </LUT1D>")?;
+        }
+        writeln!(w, r#"  <LUT3D inBitDepth="32f" outBitDepth="32f" interpolation="trilinear">"#)?;
+        writeln!(w, r#"    <Array dim="{n} {n} {n} 3">"#, n = self.size)?;
+        // Same order as the table: blue varies fastest
+        for [r, g, b] in &self.table {
+            writeln!(w, "      {r} {g} {b}")?;
+        }
+        writeln!(w, "    </Array>\n  </LUT3D>")?;
+        writeln!(w, "</ProcessList>")
+    }
+
+    fn has_default_domain(&self) -> bool {
+        self.domain_min == [0.; 3] && self.domain_max == [1.; 3]
+    }
+
+    fn has_valid_domain(&self) -> bool {
+        self.domain_min.iter().zip(&self.domain_max).all(|(min, max)| min < max)
+    }
+
+    fn is_valid(&self) -> bool {
+        self.size >= 2 && self.table.len() == self.size * self.size * self.size
+            && self.shaper.as_ref().map_or(true, |s| s.len() >= 2)
+            && self.has_valid_domain()
+    }
+
+    fn check(&self) -> io::Result<()> {
+        if self.is_valid() { Ok(()) } else { Err(invalid("table size doesn't match the LUT size")) }
+    }
+}
+
+/// Syntax error in a `.cube` file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CubeError {
+    /// 1-based line number
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CubeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for CubeError {}
+
+fn parse_floats<'a>(mut tokens: impl Iterator<Item = &'a str>) -> Option<[f32; 3]> {
+    let mut values = [0.; 3];
+    for v in &mut values {
+        *v = tokens.next()?.parse().ok()?;
+    }
+    if tokens.next().is_some() {
+        return None;
+    }
+    Some(values)
+}
+
+fn parse_size<'a>(mut tokens: impl Iterator<Item = &'a str>) -> Option<usize> {
+    let size = tokens.next()?.parse().ok()?;
+    if tokens.next().is_some() {
+        return None;
+    }
+    Some(size)
+}
+
+fn is_plain_rgb(format: PixelFormat) -> bool {
+    format.channels() == 3 && format.extra() == 0 && !format.planar() && !format.doswap() && !format.swapfirst() && !format.endian16()
+        && matches!((format.float(), format.bytes_per_channel()), (false, 1 | 2) | (true, 4 | 8))
+}
+
+fn encode(format: PixelFormat, v: f32, out: &mut Vec<u8>) {
+    match (format.float(), format.bytes_per_channel()) {
+        (false, 1) => out.push((v * 255.).round() as u8),
+        (false, 2) => out.extend_from_slice(&((v * 65535.).round() as u16).to_ne_bytes()),
+        (true, 4) => out.extend_from_slice(&v.to_ne_bytes()),
+        _ => out.extend_from_slice(&f64::from(v).to_ne_bytes()),
+    }
+}
+
+fn decode(format: PixelFormat, bytes: &[u8]) -> f32 {
+    match (format.float(), bytes.len()) {
+        (false, 1) => f32::from(bytes[0]) / 255.,
+        (false, 2) => f32::from(u16::from_ne_bytes([bytes[0], bytes[1]])) / 65535.,
+        (true, 4) => f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        _ => f64::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]) as f32,
+    }
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+#[test]
+fn cube_roundtrip() {
+    let mut lut = Lut::new(3, 3).unwrap();
+    let gamma = Curve::gamma(2.2).unwrap();
+    lut.push(OwnedStage::tone_curves(&[&gamma; 3]).unwrap()).unwrap();
+    lut.push(OwnedStage::matrix(3, 3, &[0.9, 0.1, 0., 0., 1., 0., 0., 0.2, 0.8], None).unwrap()).unwrap();
+
+    let mut exported = Lut3d::from_pipeline(&lut, 9, Some(64)).unwrap();
+    exported.title = "Warm \"look\"".into();
+    assert_eq!(64, exported.shaper.as_ref().unwrap().len());
+    let mut cube = Vec::new();
+    exported.write_cube(&mut cube).unwrap();
+    let text = String::from_utf8(cube.clone()).unwrap();
+    assert!(text.starts_with("TITLE \"Warm 'look'\"\nLUT_1D_SIZE 64\nLUT_3D_SIZE 9\n"), "{text}");
+
+    let imported = Lut3d::from_cube(&cube).unwrap();
+    assert_eq!(exported.table, imported.table);
+    assert_eq!(exported.shaper, imported.shaper);
+    let roundtrip = imported.to_pipeline().unwrap();
+    for rgb in [[0.1, 0.5, 0.9], [1., 0., 0.3]] {
+        let (mut a, mut b) = ([0.; 3], [0.; 3]);
+        lut.eval_f32(&rgb, &mut a);
+        roundtrip.eval_f32(&rgb, &mut b);
+        assert!(a.iter().zip(&b).all(|(a, b)| (a - b).abs() < 0.01), "{a:?} {b:?}");
+    }
+
+    let mut flat = Vec::new();
+    exported.write_3dl(&mut flat).unwrap();
+    let flat = String::from_utf8(flat).unwrap();
+    assert!(flat.starts_with("0 127 255 383 511 639 767 895 1023\n0 0 0\n"), "{flat}");
+    assert_eq!(1 + 9 * 9 * 9, flat.lines().count());
+    let mut clf = Vec::new();
+    exported.write_clf(&mut clf).unwrap();
+    let clf = String::from_utf8(clf).unwrap();
+    assert!(clf.contains(r#"<Array dim="9 9 9 3">"#) && clf.contains(r#"<Array dim="64 3">"#), "{clf}");
+
+    unsafe {
+        let link = imported.to_device_link();
+        assert!(!link.is_null());
+        let xform = cmsCreateTransform(link, PixelFormat::RGB_8, std::ptr::null_mut(), PixelFormat::RGB_8, Intent::Perceptual, 0);
+        assert!(!xform.is_null());
+        let sampled = Lut3d::from_transform(xform, 5).unwrap();
+        assert!((sampled.table[124][1] - 1.).abs() < 0.01, "{:?}", sampled.table[124]);
+        cmsDeleteTransform(xform);
+        cmsCloseProfile(link);
+    }
+
+    let err = Lut3d::from_cube(b"LUT_3D_SIZE 2\n0 0 0\n1 1\n").unwrap_err();
+    assert_eq!(3, err.line);
+    let err = Lut3d::from_cube(b"LUT_3D_SIZE 2\n0 0 0\n").unwrap_err();
+    assert_eq!("line 2: expected 8 rows of data, found 1", err.to_string());
+}
+
+#[test]
+fn read_cube_input_ranges() {
+    let cube = "# Comment\nTITLE \"Swap\"\nLUT_3D_SIZE 2\nLUT_3D_INPUT_RANGE 0.0 1.0\n\n\
+        0 0 0\n0 0 1\n0 1 0\n0 1 1\n1 0 0\n1 0 1\n1 1 0\n1 1 1\n";
+    let swap = Lut3d::read_cube(io::Cursor::new(cube)).unwrap();
+    assert_eq!("Swap", swap.title);
+    assert_eq!([0., 0., 1.], swap.table[4]);
+    let err = Lut3d::read_cube(&b"LUT_3D_SIZE 2\nLUT_3D_INPUT_RANGE 0 2\n"[..]).unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    assert_eq!(2, err.get_ref().unwrap().downcast_ref::<CubeError>().unwrap().line);
+    unsafe {
+        let context = cmsCreateContext(ptr::null_mut(), ptr::null_mut());
+        let link = swap.to_device_link_thr(context);
+        assert!(!link.is_null());
+        assert_eq!(context, cmsGetProfileContextID(link));
+        let xform = cmsCreateTransformTHR(context, link, PixelFormat::RGB_8, ptr::null_mut(), PixelFormat::RGB_8, Intent::Perceptual, 0);
+        let mut pixel = [255u8, 0, 51];
+        cmsDoTransform(xform, pixel.as_ptr().cast(), pixel.as_mut_ptr().cast(), 1);
+        assert_eq!([51, 0, 255], pixel);
+        cmsDeleteTransform(xform);
+        cmsCloseProfile(link);
+        cmsDeleteContext(context);
+    }
+
+    // TITLE must be the whole keyword
+    let err = Lut3d::from_cube(b"TITLEX \"a\"\nLUT_3D_SIZE 2\n").unwrap_err();
+    assert_eq!("line 1: unsupported keyword", err.to_string());
+
+    // 1D-only files get an identity 3D table
+    let curve = Lut3d::from_cube(b"LUT_1D_SIZE 3\nLUT_1D_INPUT_RANGE 0 1\n0 0 0\n0.25 0.5 0.75\n1 1 1\n").unwrap();
+    assert_eq!(2, curve.size);
+    let mut out = [0.; 3];
+    curve.to_pipeline().unwrap().eval_f32(&[0.5; 3], &mut out);
+    assert!(out.iter().zip([0.25, 0.5, 0.75]).all(|(a, b)| (a - b).abs() < 0.001), "{out:?}");
+}
@@ -0,0 +1,839 @@
+//! Owned pipelines and stages that free themselves.
+
+use crate::ffi::*;
+use crate::tone_curve::Curve;
+use std::any::Any;
+use std::fmt;
+use std::marker::PhantomData;
+use std::os::raw::c_void;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::ptr::{self, NonNull};
+use std::slice;
+
+// The public binding returns `StageSignature`, which can't hold signatures of plug-in stages
+#[allow(clashing_extern_declarations)]
+extern "C" {
+    #[link_name = "cmsStageType"]
+    fn cmsStageTypeRaw(mpe: *const Stage) -> Signature;
+}
+
+/// Owned `*mut Pipeline`, freed with `cmsPipelineFree`
+pub struct Lut(NonNull<Pipeline>);
+
+// Evaluation doesn't mutate the pipeline
+unsafe impl Send for Lut {}
+unsafe impl Sync for Lut {}
+
+impl Lut {
+    /// Empty pipeline. It passes values through until stages are added.
+    #[must_use]
+    pub fn new(inputs: u32, outputs: u32) -> Option<Self> {
+        unsafe { Self::new_thr(ptr::null_mut(), inputs, outputs) }
+    }
+
+    /// Same as [`Lut::new`], allocated in the `context`
+    ///
+    /// # Safety
+    ///
+    /// `context` must be valid or null.
+    #[must_use]
+    pub unsafe fn new_thr(context: Context, inputs: u32, outputs: u32) -> Option<Self> {
+        Self::from_ptr(cmsPipelineAlloc(context, inputs, outputs))
+    }
+
+    /// Takes ownership of the pipeline. `None` if it's null.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be a valid pipeline that nothing else will free.
+    #[must_use]
+    pub unsafe fn from_ptr(lut: *mut Pipeline) -> Option<Self> {
+        NonNull::new(lut).map(Self)
+    }
+
+    /// The pipeline stays owned by `self`
+    #[must_use]
+    pub fn as_ptr(&self) -> *mut Pipeline {
+        self.0.as_ptr()
+    }
+
+    /// Gives up ownership. The caller has to free the pipeline.
+    #[must_use]
+    pub fn into_ptr(self) -> *mut Pipeline {
+        let ptr = self.0.as_ptr();
+        std::mem::forget(self);
+        ptr
+    }
+
+    #[must_use]
+    pub fn inputs(&self) -> usize {
+        unsafe { cmsPipelineInputChannels(self.as_ptr()) as usize }
+    }
+
+    #[must_use]
+    pub fn outputs(&self) -> usize {
+        unsafe { cmsPipelineOutputChannels(self.as_ptr()) as usize }
+    }
+
+    #[must_use]
+    pub fn stage_count(&self) -> usize {
+        unsafe { cmsPipelineStageCount(self.as_ptr()) as usize }
+    }
+
+    /// Adds the stage at the end. Gives the stage back if its inputs don't match outputs of the last stage.
+    pub fn push(&mut self, stage: OwnedStage) -> Result<(), OwnedStage> {
+        if let Some(last) = self.stages().last() {
+            if last.outputs() != stage.as_stage().inputs() {
+                return Err(stage);
+            }
+        }
+        unsafe { cmsPipelineInsertStage(self.as_ptr(), StageLoc::AT_END, stage.into_ptr()); }
+        Ok(())
+    }
+
+    /// Adds the stage at the beginning. Gives the stage back if its outputs don't match inputs of the first stage.
+    pub fn prepend(&mut self, stage: OwnedStage) -> Result<(), OwnedStage> {
+        if let Some(first) = self.stages().next() {
+            if first.inputs() != stage.as_stage().outputs() {
+                return Err(stage);
+            }
+        }
+        unsafe { cmsPipelineInsertStage(self.as_ptr(), StageLoc::AT_BEGIN, stage.into_ptr()); }
+        Ok(())
+    }
+
+    /// Appends copies of all stages of `other` (`cmsPipelineCat`). Returns `false` if channels don't match.
+    pub fn cat(&mut self, other: &Self) -> bool {
+        if self.stage_count() > 0 && other.stage_count() > 0 && self.outputs() != other.inputs() {
+            return false;
+        }
+        unsafe { cmsPipelineCat(self.as_ptr(), other.as_ptr()) != 0 }
+    }
+
+    /// Evaluates in 16 bits (`cmsPipelineEval16`)
+    ///
+    /// # Panics
+    ///
+    /// If slices are shorter than number of input or output channels
+    pub fn eval16(&self, input: &[u16], output: &mut [u16]) {
+        assert!(input.len() >= self.inputs() && output.len() >= self.outputs());
+        unsafe { cmsPipelineEval16(input.as_ptr(), output.as_mut_ptr(), self.as_ptr()) }
+    }
+
+    /// Evaluates in floating point (`cmsPipelineEvalFloat`)
+    ///
+    /// # Panics
+    ///
+    /// If slices are shorter than number of input or output channels
+    pub fn eval_f32(&self, input: &[f32], output: &mut [f32]) {
+        assert!(input.len() >= self.inputs() && output.len() >= self.outputs());
+        unsafe { cmsPipelineEvalFloat(input.as_ptr(), output.as_mut_ptr(), self.as_ptr()) }
+    }
+
+    /// Finds input that gives `target` output, using Newton's method like `cmsPipelineEvalReverseFloat`, and reports how well it went.
+    ///
+    /// Only 3→3 and 4→3 pipelines are supported, otherwise it's `None`. It's `None` too if the pipeline gives
+    /// NaN or infinite output, since then the error can't be compared. For 4 inputs the `target` has 4 values,
+    /// and the last one is the fixed 4th input (e.g. K of CMYK). The search starts at `hint`, or 0.3 of each input,
+    /// and stops when the error is within `tolerance`, or doesn't improve any more.
+    ///
+    /// # Panics
+    ///
+    /// If `target` is shorter than the number of inputs
+    #[must_use]
+    pub fn eval_reverse(&self, target: &[f32], hint: Option<[f32; 3]>, tolerance: f32) -> Option<Reverse> {
+        let inputs = self.inputs();
+        if (inputs != 3 && inputs != 4) || self.outputs() != 3 {
+            return None;
+        }
+        assert!(target.len() >= inputs);
+        let [x0, x1, x2] = hint.unwrap_or([0.3; 3]);
+        let mut x = [x0, x1, x2, if inputs == 4 { target[3] } else { 0. }];
+        let mut best = Reverse { input: x, error: f32::INFINITY, iterations: 0, converged: false };
+        let mut fx = [0.; 3];
+        while best.iterations < REVERSE_MAX_ITERATIONS {
+            self.eval_f32(&x, &mut fx);
+            let error = fx.iter().zip(target).map(|(a, b)| (a - b) * (a - b)).sum::<f32>().sqrt();
+            if !error.is_finite() {
+                return None;
+            }
+            if error >= best.error {
+                break;
+            }
+            best.iterations += 1;
+            best.input = x;
+            best.error = error;
+            if error <= tolerance {
+                best.converged = true;
+                break;
+            }
+
+            let mut jacobian = MAT3::default();
+            for j in 0..3 {
+                let mut xd = x;
+                // Step away from the edge of the domain
+                xd[j] += if xd[j] < 1. - JACOBIAN_EPSILON { JACOBIAN_EPSILON } else { -JACOBIAN_EPSILON };
+                let mut fxd = [0.; 3];
+                self.eval_f32(&xd, &mut fxd);
+                for (row, (d, f)) in jacobian.v.iter_mut().zip(fxd.iter().zip(&fx)) {
+                    row.n[j] = f64::from((d - f) / (xd[j] - x[j]));
+                }
+            }
+            let mut diff = VEC3 { n: [0, 1, 2].map(|i| f64::from(fx[i] - target[i])) };
+            let mut step = VEC3::default();
+            if unsafe { _cmsMAT3solve(&mut step, &mut jacobian, &mut diff) } == 0 {
+                break;
+            }
+            for (x, s) in x.iter_mut().zip(step.n) {
+                *x = (*x - s as f32).clamp(0., 1.);
+            }
+        }
+        Some(best)
+    }
+
+    /// [`Lut::eval_reverse`] for every target. `targets` has 3 or 4 values per target, as many as the pipeline has inputs.
+    ///
+    /// # Panics
+    ///
+    /// If the length of `targets` isn't a multiple of the number of inputs
+    #[must_use]
+    pub fn eval_reverse_batch(&self, targets: &[f32], tolerance: f32) -> Option<Vec<Reverse>> {
+        let inputs = self.inputs();
+        if inputs == 0 {
+            return None;
+        }
+        assert_eq!(0, targets.len() % inputs, "targets must have {inputs} values each");
+        targets.chunks_exact(inputs).map(|target| self.eval_reverse(target, None, tolerance)).collect()
+    }
+
+    /// Stages from first to last
+    #[must_use]
+    pub fn stages(&self) -> Stages<'_> {
+        unsafe { Stages::from_pipeline(self.as_ptr()) }
+    }
+
+    /// The stages, if their types are exactly `types`, in order. Replaces `cmsPipelineCheckAndRetreiveStages`.
+    #[must_use]
+    pub fn match_stages(&self, types: &[StageSignature]) -> Option<Vec<StageRef<'_>>> {
+        let stages: Vec<_> = self.stages().collect();
+        if stages.len() != types.len() || stages.iter().zip(types).any(|(s, &t)| s.stage_type() != t as Signature) {
+            return None;
+        }
+        Some(stages)
+    }
+}
+
+impl Clone for Lut {
+    /// # Panics
+    ///
+    /// If LCMS runs out of memory
+    fn clone(&self) -> Self {
+        unsafe { Self::from_ptr(cmsPipelineDup(self.as_ptr())) }.expect("cmsPipelineDup")
+    }
+}
+
+impl Drop for Lut {
+    fn drop(&mut self) {
+        unsafe { cmsPipelineFree(self.as_ptr()) }
+    }
+}
+
+impl fmt::Debug for Lut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lut")
+            .field("inputs", &self.inputs())
+            .field("outputs", &self.outputs())
+            .field("stages", &self.stages().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+const REVERSE_MAX_ITERATIONS: u32 = 30;
+const JACOBIAN_EPSILON: f32 = 0.001;
+
+/// Result of [`Lut::eval_reverse`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Reverse {
+    /// Best input found. The 4th value is only used by 4-input pipelines.
+    pub input: [f32; 4],
+    /// Euclidean distance between the target and output for `input`
+    pub error: f32,
+    /// Number of iterations that improved the result, including the starting point
+    pub iterations: u32,
+    /// `error` is within the tolerance. If not, `input` is only the closest point found.
+    pub converged: bool,
+}
+
+/// Iterator returned by [`Lut::stages`]
+pub struct Stages<'a> {
+    next: *mut Stage,
+    _lut: PhantomData<&'a Lut>,
+}
+
+impl Stages<'_> {
+    /// # Safety
+    ///
+    /// The pipeline must stay valid and keep its stages while they're borrowed.
+    pub(crate) unsafe fn from_pipeline(lut: *mut Pipeline) -> Self {
+        Self {
+            next: cmsPipelineGetPtrToFirstStage(lut),
+            _lut: PhantomData,
+        }
+    }
+}
+
+impl<'a> Iterator for Stages<'a> {
+    type Item = StageRef<'a>;
+
+    fn next(&mut self) -> Option<StageRef<'a>> {
+        let stage = NonNull::new(self.next)?;
+        self.next = unsafe { cmsStageNext(stage.as_ptr()) };
+        Some(StageRef { stage, _owner: PhantomData })
+    }
+}
+
+/// What a stage does, from its `cmsStageType`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StageKind {
+    /// One tone curve per channel
+    CurveSet,
+    /// Matrix with an optional offset
+    Matrix,
+    /// Multidimensional lookup table
+    CLut,
+    Identity,
+    /// Signature of a fixed conversion (e.g. `XYZ2LabElemType`), or of a stage from a plug-in
+    Other(Signature),
+}
+
+/// Stage borrowed from a [`Lut`] or an [`OwnedStage`]
+#[derive(Copy, Clone)]
+pub struct StageRef<'a> {
+    stage: NonNull<Stage>,
+    _owner: PhantomData<&'a Stage>,
+}
+
+impl<'a> StageRef<'a> {
+    /// The stage stays owned by its pipeline
+    #[must_use]
+    pub fn as_ptr(&self) -> *mut Stage {
+        self.stage.as_ptr()
+    }
+
+    #[must_use]
+    pub fn inputs(&self) -> usize {
+        unsafe { cmsStageInputChannels(self.as_ptr()) as usize }
+    }
+
+    #[must_use]
+    pub fn outputs(&self) -> usize {
+        unsafe { cmsStageOutputChannels(self.as_ptr()) as usize }
+    }
+
+    /// `cmsStageType`, e.g. `StageSignature::MatrixElemType as Signature`, or a signature defined by a plug-in
+    #[must_use]
+    pub fn stage_type(&self) -> Signature {
+        unsafe { cmsStageTypeRaw(self.as_ptr()) }
+    }
+
+    #[must_use]
+    pub fn kind(&self) -> StageKind {
+        match self.stage_type() {
+            t if t == StageSignature::CurveSetElemType as Signature => StageKind::CurveSet,
+            t if t == StageSignature::MatrixElemType as Signature => StageKind::Matrix,
+            t if t == StageSignature::CLutElemType as Signature => StageKind::CLut,
+            t if t == StageSignature::IdentityElemType as Signature => StageKind::Identity,
+            other => StageKind::Other(other),
+        }
+    }
+
+    /// Copies of the curves of a `CurveSet` stage, one per channel
+    #[must_use]
+    pub fn curves(&self) -> Option<Vec<Curve>> {
+        if self.kind() != StageKind::CurveSet {
+            return None;
+        }
+        unsafe {
+            let data = &*cmsStageData(self.as_ptr()).cast::<StageToneCurvesData>();
+            slice::from_raw_parts(data.TheCurves, data.nCurves as usize).iter()
+                .map(|&c| Curve::from_ptr(cmsDupToneCurve(c)))
+                .collect()
+        }
+    }
+
+    /// Coefficients of a `Matrix` stage
+    #[must_use]
+    pub fn matrix(&self) -> Option<MatrixRef<'a>> {
+        if self.kind() != StageKind::Matrix {
+            return None;
+        }
+        let data = unsafe { &*cmsStageData(self.as_ptr()).cast::<StageMatrixData>() };
+        Some(MatrixRef { data, rows: self.outputs(), cols: self.inputs() })
+    }
+
+    /// Grid and table of a `CLut` stage
+    #[must_use]
+    pub fn clut(&self) -> Option<ClutRef<'a>> {
+        if self.kind() != StageKind::CLut {
+            return None;
+        }
+        let data = unsafe { &*cmsStageData(self.as_ptr()).cast::<StageCLutData>() };
+        Some(ClutRef { data, inputs: self.inputs(), outputs: self.outputs() })
+    }
+
+    /// Calls `f` with inputs and outputs of every node of a 16-bit `CLut` stage (`cmsStageSampleCLut16bit` with `SAMPLER_INSPECT`).
+    ///
+    /// Stops when `f` returns `false`. Returns `false` if stopped, or if it's not a 16-bit CLUT.
+    pub fn inspect16(&self, mut f: impl FnMut(&[u16], &[u16]) -> bool) -> bool {
+        if !matches!(self.clut().map(|c| c.table()), Some(ClutTable::U16(_))) {
+            return false;
+        }
+        unsafe { sample_clut16(self.as_ptr(), &mut |i: &[u16], o: &mut [u16]| f(i, o), SAMPLER_INSPECT) }
+    }
+
+    /// Same as [`StageRef::inspect16`], but for a floating-point `CLut` stage (`cmsStageSampleCLutFloat`)
+    pub fn inspect_f32(&self, mut f: impl FnMut(&[f32], &[f32]) -> bool) -> bool {
+        if !matches!(self.clut().map(|c| c.table()), Some(ClutTable::F32(_))) {
+            return false;
+        }
+        unsafe { sample_clut_f32(self.as_ptr(), &mut |i: &[f32], o: &mut [f32]| f(i, o), SAMPLER_INSPECT) }
+    }
+
+    /// Copy of the stage that can be added to another pipeline
+    #[must_use]
+    pub fn to_owned(&self) -> Option<OwnedStage> {
+        unsafe { OwnedStage::from_ptr(cmsStageDup(self.as_ptr())) }
+    }
+}
+
+impl fmt::Debug for StageRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Stage")
+            .field("kind", &self.kind())
+            .field("inputs", &self.inputs())
+            .field("outputs", &self.outputs())
+            .finish()
+    }
+}
+
+/// Data of a matrix stage (`StageMatrixData`)
+#[derive(Copy, Clone)]
+pub struct MatrixRef<'a> {
+    data: &'a StageMatrixData,
+    rows: usize,
+    cols: usize,
+}
+
+impl<'a> MatrixRef<'a> {
+    /// Number of outputs
+    #[must_use]
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Number of inputs
+    #[must_use]
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// `rows` × `cols` coefficients in row-major order
+    #[must_use]
+    pub fn coefficients(&self) -> &'a [f64] {
+        unsafe { slice::from_raw_parts(self.data.Double, self.rows * self.cols) }
+    }
+
+    /// One value added to each output, if there is an offset
+    #[must_use]
+    pub fn offset(&self) -> Option<&'a [f64]> {
+        if self.data.Offset.is_null() {
+            return None;
+        }
+        Some(unsafe { slice::from_raw_parts(self.data.Offset, self.rows) })
+    }
+}
+
+impl fmt::Debug for MatrixRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Matrix")
+            .field("rows", &self.rows)
+            .field("cols", &self.cols)
+            .field("coefficients", &self.coefficients())
+            .field("offset", &self.offset())
+            .finish()
+    }
+}
+
+/// Table of a CLUT stage, in the precision it was created with
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ClutTable<'a> {
+    U16(&'a [u16]),
+    F32(&'a [f32]),
+}
+
+/// Data of a CLUT stage (`StageCLutData`)
+#[derive(Copy, Clone)]
+pub struct ClutRef<'a> {
+    data: &'a StageCLutData,
+    inputs: usize,
+    outputs: usize,
+}
+
+impl<'a> ClutRef<'a> {
+    #[must_use]
+    pub fn inputs(&self) -> usize {
+        self.inputs
+    }
+
+    /// Number of values in each node
+    #[must_use]
+    pub fn outputs(&self) -> usize {
+        self.outputs
+    }
+
+    /// Number of nodes along each input
+    #[must_use]
+    pub fn grid_points(&self) -> &'a [u32] {
+        let params: &'a InterpParams = unsafe { &*self.data.Params };
+        &params.nSamples[..self.inputs]
+    }
+
+    /// All nodes. The first input varies slowest, and each node has `outputs()` values.
+    #[must_use]
+    pub fn table(&self) -> ClutTable<'a> {
+        let len = self.data.nEntries as usize;
+        unsafe {
+            if self.data.HasFloatValues != 0 {
+                ClutTable::F32(if len > 0 { slice::from_raw_parts(self.data.Tab.TFloat, len) } else { &[] })
+            } else {
+                ClutTable::U16(if len > 0 { slice::from_raw_parts(self.data.Tab.T, len) } else { &[] })
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ClutRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Clut")
+            .field("grid_points", &self.grid_points())
+            .field("outputs", &self.outputs)
+            .finish()
+    }
+}
+
+/// Owned `*mut Stage` that isn't in any pipeline yet, freed with `cmsStageFree`
+pub struct OwnedStage(NonNull<Stage>);
+
+unsafe impl Send for OwnedStage {}
+unsafe impl Sync for OwnedStage {}
+
+impl OwnedStage {
+    /// Takes ownership of the stage. `None` if it's null.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be a valid stage that isn't in a pipeline, and that nothing else will free.
+    #[must_use]
+    pub unsafe fn from_ptr(stage: *mut Stage) -> Option<Self> {
+        NonNull::new(stage).map(Self)
+    }
+
+    /// Gives up ownership. The caller has to free the stage, or insert it into a pipeline.
+    #[must_use]
+    pub fn into_ptr(self) -> *mut Stage {
+        let ptr = self.0.as_ptr();
+        std::mem::forget(self);
+        ptr
+    }
+
+    #[must_use]
+    pub fn as_stage(&self) -> StageRef<'_> {
+        StageRef { stage: self.0, _owner: PhantomData }
+    }
+
+    /// `cmsStageAllocIdentity`
+    #[must_use]
+    pub fn identity(channels: u32) -> Option<Self> {
+        unsafe { Self::from_ptr(cmsStageAllocIdentity(ptr::null_mut(), channels)) }
+    }
+
+    /// One curve per channel (`cmsStageAllocToneCurves`). The stage has its own copies of the curves.
+    #[must_use]
+    pub fn tone_curves(curves: &[&Curve]) -> Option<Self> {
+        let ptrs: Vec<_> = curves.iter().map(|c| c.as_ptr() as *const ToneCurve).collect();
+        unsafe { Self::from_ptr(cmsStageAllocToneCurves(ptr::null_mut(), ptrs.len() as u32, ptrs.as_ptr())) }
+    }
+
+    /// `rows` outputs × `cols` inputs matrix in row-major order, and optionally `rows` offsets (`cmsStageAllocMatrix`).
+    ///
+    /// `None` if the slices have wrong lengths.
+    #[must_use]
+    pub fn matrix(rows: u32, cols: u32, matrix: &[f64], offset: Option<&[f64]>) -> Option<Self> {
+        if matrix.len() != (rows * cols) as usize || offset.map_or(false, |o| o.len() != rows as usize) {
+            return None;
+        }
+        let offset = offset.map_or(ptr::null(), |o| o.as_ptr());
+        unsafe { Self::from_ptr(cmsStageAllocMatrix(ptr::null_mut(), rows, cols, matrix.as_ptr(), offset)) }
+    }
+
+    /// CLUT with `grid_points` per input (`cmsStageAllocCLut16bitGranular`).
+    ///
+    /// The table has `outputs` values per node, and the first input varies slowest. Without the table, all nodes are 0.
+    /// `None` if the table has a wrong length.
+    #[must_use]
+    pub fn clut16(grid_points: &[u32], outputs: u32, table: Option<&[u16]>) -> Option<Self> {
+        if table.map_or(false, |t| t.len() != clut_len(grid_points, outputs)) {
+            return None;
+        }
+        let table = table.map_or(ptr::null(), |t| t.as_ptr());
+        unsafe { Self::from_ptr(cmsStageAllocCLut16bitGranular(ptr::null_mut(), grid_points.as_ptr(), grid_points.len() as u32, outputs, table)) }
+    }
+
+    /// Same as [`OwnedStage::clut16`], but in floating point (`cmsStageAllocCLutFloatGranular`)
+    #[must_use]
+    pub fn clut_f32(grid_points: &[u32], outputs: u32, table: Option<&[f32]>) -> Option<Self> {
+        if table.map_or(false, |t| t.len() != clut_len(grid_points, outputs)) {
+            return None;
+        }
+        let table = table.map_or(ptr::null(), |t| t.as_ptr());
+        unsafe { Self::from_ptr(cmsStageAllocCLutFloatGranular(ptr::null_mut(), grid_points.as_ptr(), grid_points.len() as u32, outputs, table)) }
+    }
+}
+
+impl OwnedStage {
+    /// Sets every node of a 16-bit `CLut` stage (`cmsStageSampleCLut16bit`).
+    ///
+    /// `f` gets inputs of the node, and its current values to overwrite. Stops when `f` returns `false`.
+    /// Returns `false` if stopped, or if it's not a 16-bit CLUT. Panics in `f` are resumed after LCMS returns.
+    pub fn sample16(&mut self, mut f: impl FnMut(&[u16], &mut [u16]) -> bool) -> bool {
+        if !matches!(self.as_stage().clut().map(|c| c.table()), Some(ClutTable::U16(_))) {
+            return false;
+        }
+        unsafe { sample_clut16(self.0.as_ptr(), &mut f, 0) }
+    }
+
+    /// Same as [`OwnedStage::sample16`], but for a floating-point `CLut` stage (`cmsStageSampleCLutFloat`)
+    pub fn sample_f32(&mut self, mut f: impl FnMut(&[f32], &mut [f32]) -> bool) -> bool {
+        if !matches!(self.as_stage().clut().map(|c| c.table()), Some(ClutTable::F32(_))) {
+            return false;
+        }
+        unsafe { sample_clut_f32(self.0.as_ptr(), &mut f, 0) }
+    }
+}
+
+/// Calls `f` for every node of a grid with `grid_points` per input (`cmsSliceSpace16`).
+///
+/// Stops when `f` returns `false`. Returns `false` if stopped or the grid is invalid.
+pub fn slice_space16(grid_points: &[u32], mut f: impl FnMut(&[u16]) -> bool) -> bool {
+    let mut cargo = Cargo { f: &mut |i: &[u16], _: &mut [u16]| f(i), inputs: grid_points.len(), outputs: 0, panic: None };
+    let res = unsafe { cmsSliceSpace16(grid_points.len() as u32, grid_points.as_ptr(), sampler::<u16>, cargo.as_mut_ptr()) };
+    cargo.finish(res)
+}
+
+/// Same as [`slice_space16`], but with inputs in 0..=1 range (`cmsSliceSpaceFloat`)
+pub fn slice_space_f32(grid_points: &[u32], mut f: impl FnMut(&[f32]) -> bool) -> bool {
+    let mut cargo = Cargo { f: &mut |i: &[f32], _: &mut [f32]| f(i), inputs: grid_points.len(), outputs: 0, panic: None };
+    let res = unsafe { cmsSliceSpaceFloat(grid_points.len() as u32, grid_points.as_ptr(), sampler::<f32>, cargo.as_mut_ptr()) };
+    cargo.finish(res)
+}
+
+unsafe fn sample_clut16(stage: *mut Stage, f: &mut dyn FnMut(&[u16], &mut [u16]) -> bool, flags: u32) -> bool {
+    let mut cargo = Cargo { f, inputs: cmsStageInputChannels(stage) as usize, outputs: cmsStageOutputChannels(stage) as usize, panic: None };
+    let res = cmsStageSampleCLut16bit(stage, sampler::<u16>, cargo.as_mut_ptr(), flags);
+    cargo.finish(res)
+}
+
+unsafe fn sample_clut_f32(stage: *mut Stage, f: &mut dyn FnMut(&[f32], &mut [f32]) -> bool, flags: u32) -> bool {
+    let mut cargo = Cargo { f, inputs: cmsStageInputChannels(stage) as usize, outputs: cmsStageOutputChannels(stage) as usize, panic: None };
+    let res = cmsStageSampleCLutFloat(stage, sampler::<f32>, cargo.as_mut_ptr(), flags);
+    cargo.finish(res)
+}
+
+/// User data of samplers. A panic stops sampling, and is resumed once LCMS has returned.
+struct Cargo<'a, T> {
+    f: &'a mut dyn FnMut(&[T], &mut [T]) -> bool,
+    inputs: usize,
+    outputs: usize,
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+impl<T> Cargo<'_, T> {
+    fn as_mut_ptr(&mut self) -> *mut c_void {
+        (self as *mut Self).cast()
+    }
+
+    fn finish(self, res: Bool) -> bool {
+        if let Some(panic) = self.panic {
+            resume_unwind(panic);
+        }
+        res != 0
+    }
+}
+
+unsafe extern "C" fn sampler<T>(input: *const T, output: *mut T, cargo: *mut c_void) -> i32 {
+    let cargo = &mut *cargo.cast::<Cargo<'_, T>>();
+    let input = slice::from_raw_parts(input, cargo.inputs);
+    // Slicing the space has no outputs, and passes null
+    let output: &mut [T] = if output.is_null() { &mut [] } else { slice::from_raw_parts_mut(output, cargo.outputs) };
+    match catch_unwind(AssertUnwindSafe(|| (cargo.f)(input, output))) {
+        Ok(res) => res.into(),
+        Err(panic) => {
+            cargo.panic = Some(panic);
+            0
+        },
+    }
+}
+
+fn clut_len(grid_points: &[u32], outputs: u32) -> usize {
+    grid_points.iter().map(|&n| n as usize).product::<usize>() * outputs as usize
+}
+
+impl Clone for OwnedStage {
+    /// # Panics
+    ///
+    /// If LCMS runs out of memory
+    fn clone(&self) -> Self {
+        self.as_stage().to_owned().expect("cmsStageDup")
+    }
+}
+
+impl Drop for OwnedStage {
+    fn drop(&mut self) {
+        unsafe { cmsStageFree(self.0.as_ptr()) }
+    }
+}
+
+impl fmt::Debug for OwnedStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_stage().fmt(f)
+    }
+}
+
+#[test]
+fn build_and_inspect() {
+    let gamma = Curve::gamma(2.).unwrap();
+    let mut lut = Lut::new(3, 3).unwrap();
+    lut.push(OwnedStage::tone_curves(&[&gamma; 3]).unwrap()).unwrap();
+    // Has 1 output, but the curves need 3 inputs
+    let rejected = lut.prepend(OwnedStage::matrix(1, 3, &[1.; 3], None).unwrap());
+    assert!(rejected.is_err());
+    // Weighted sum of channels
+    lut.push(OwnedStage::matrix(1, 3, &[0.5, 0.25, 0.25], Some(&[0.])).unwrap()).unwrap();
+    assert_eq!((3, 1, 2), (lut.inputs(), lut.outputs(), lut.stage_count()));
+    assert!(OwnedStage::clut16(&[2, 2], 1, Some(&[0; 3])).is_none());
+
+    let mut out = [0f32];
+    lut.eval_f32(&[0.5, 1., 0.], &mut out);
+    assert!((out[0] - 0.375).abs() < 1e-4, "{out:?}");
+
+    let mut tail = Lut::new(1, 1).unwrap();
+    tail.push(OwnedStage::clut16(&[2], 1, Some(&[0xFFFF, 0])).unwrap()).unwrap();
+    assert!(lut.cat(&tail));
+    assert!(!lut.cat(&lut.clone()));
+    let mut out = [0u16];
+    lut.eval16(&[0, 0, 0], &mut out);
+    assert_eq!([0xFFFF], out);
+
+    let kinds: Vec<_> = lut.stages().map(|s| s.kind()).collect();
+    assert_eq!(vec![StageKind::CurveSet, StageKind::Matrix, StageKind::CLut], kinds);
+    let stages = lut.match_stages(&[StageSignature::CurveSetElemType, StageSignature::MatrixElemType, StageSignature::CLutElemType]).unwrap();
+    assert_eq!(1, stages[2].inputs());
+    assert!(lut.match_stages(&[StageSignature::CurveSetElemType]).is_none());
+
+    let curves = stages[0].curves().unwrap();
+    assert_eq!(3, curves.len());
+    assert_eq!(Some(2.), curves[0].params().map(|p| p[0]));
+    let matrix = stages[1].matrix().unwrap();
+    assert_eq!((1, 3), (matrix.rows(), matrix.cols()));
+    assert_eq!(&[0.5, 0.25, 0.25], matrix.coefficients());
+    assert_eq!(Some(&[0.][..]), matrix.offset());
+    let clut = stages[2].clut().unwrap();
+    assert_eq!(&[2], clut.grid_points());
+    assert_eq!(ClutTable::U16(&[0xFFFF, 0]), clut.table());
+    assert!(stages[0].clut().is_none());
+
+    let clut = OwnedStage::clut_f32(&[2, 3], 1, Some(&[0., 0.1, 0.2, 0.3, 0.4, 0.5])).unwrap();
+    let clut = clut.as_stage();
+    assert_eq!(&[2, 3], clut.clut().unwrap().grid_points());
+    assert_eq!(ClutTable::F32(&[0., 0.1, 0.2, 0.3, 0.4, 0.5]), clut.clut().unwrap().table());
+
+    // Signature the `StageSignature` enum doesn't have, like a plug-in's
+    unsafe extern "C" fn invert(input: *const f32, output: *mut f32, _: *const Stage) {
+        *output = 1. - *input;
+    }
+    let custom = unsafe {
+        OwnedStage::from_ptr(_cmsStageAllocPlaceholder(ptr::null_mut(), 0x696e_7674, 1, 1, Some(invert), None, None, ptr::null_mut())).unwrap()
+    };
+    assert_eq!(StageKind::Other(0x696e_7674), custom.as_stage().kind());
+}
+
+#[test]
+fn sample_with_closures() {
+    // Ink limit: scale CMY down so that their sum is at most 200%
+    let mut clut = OwnedStage::clut16(&[3, 3, 3], 3, None).unwrap();
+    assert!(clut.sample16(|input, output| {
+        let sum: u32 = input.iter().map(|&v| u32::from(v)).sum();
+        let scale = (2. * 65535. / sum.max(1) as f64).min(1.);
+        for (o, &i) in output.iter_mut().zip(input) {
+            *o = (f64::from(i) * scale).round() as u16;
+        }
+        true
+    }));
+
+    let mut nodes = 0;
+    assert!(clut.as_stage().inspect16(|input, output| {
+        nodes += 1;
+        assert!(output.iter().map(|&v| u32::from(v)).sum::<u32>() <= 2 * 65535 + 1, "{input:?} {output:?}");
+        true
+    }));
+    assert_eq!(27, nodes);
+    assert!(!clut.as_stage().inspect_f32(|_, _| true));
+
+    let mut clut_f32 = OwnedStage::clut_f32(&[2, 2], 1, None).unwrap();
+    assert!(clut_f32.sample_f32(|input, output| { output[0] = input[0] * input[1]; true }));
+    assert_eq!(ClutTable::F32(&[0., 0., 0., 1.]), clut_f32.as_stage().clut().unwrap().table());
+
+    let mut visited = Vec::new();
+    assert!(!slice_space_f32(&[2, 2], |input| { visited.push(input.to_vec()); visited.len() < 3 }));
+    assert_eq!(vec![vec![0., 0.], vec![0., 1.], vec![1., 0.]], visited);
+    let mut count = 0;
+    assert!(slice_space16(&[17, 5], |_| { count += 1; true }));
+    assert_eq!(85, count);
+
+    let panicked = std::panic::catch_unwind(|| slice_space16(&[2], |_| panic!("sampler")));
+    assert_eq!(Some(&"sampler"), panicked.unwrap_err().downcast_ref::<&str>());
+}
+
+#[test]
+fn reverse_eval() {
+    let mut lut = Lut::new(3, 3).unwrap();
+    let curves = [&Curve::gamma(2.2).unwrap(), &Curve::gamma(1.8).unwrap(), &Curve::gamma(1.).unwrap()];
+    lut.push(OwnedStage::tone_curves(&curves).unwrap()).unwrap();
+    lut.push(OwnedStage::matrix(3, 3, &[0.8, 0.1, 0.1, 0.2, 0.7, 0.1, 0., 0.1, 0.9], None).unwrap()).unwrap();
+
+    let mut target = [0f32; 3];
+    lut.eval_f32(&[0.2, 0.5, 0.7], &mut target);
+    let res = lut.eval_reverse(&target, None, 1e-4).unwrap();
+    assert!(res.converged && res.error <= 1e-4 && res.iterations > 1, "{res:?}");
+    for (found, expected) in res.input.iter().zip([0.2, 0.5, 0.7]) {
+        assert!((found - expected).abs() < 0.01, "{res:?}");
+    }
+
+    // Out of gamut, can't be reached with inputs in 0..=1
+    let res = lut.eval_reverse(&[2., 2., 2.], Some([0.9; 3]), 1e-4).unwrap();
+    assert!(!res.converged && res.error > 0.5, "{res:?}");
+
+    let batch = lut.eval_reverse_batch(&[target[0], target[1], target[2], 0., 0., 0.], 1e-4).unwrap();
+    assert_eq!(2, batch.len());
+    assert!(batch[1].converged && batch[1].input[..3].iter().all(|&v| v < 0.01), "{batch:?}");
+    assert!(Lut::new(1, 1).unwrap().eval_reverse(&[0.], None, 0.).is_none());
+
+    // NaN error never compares as worse, so it must not be taken as progress
+    let mut nan = Lut::new(3, 3).unwrap();
+    nan.push(OwnedStage::matrix(3, 3, &[1., 0., 0., 0., 1., 0., 0., 0., 1.], Some(&[f64::NAN, 0., 0.])).unwrap()).unwrap();
+    let mut out = [0f32; 3];
+    nan.eval_f32(&[0.5; 3], &mut out);
+    assert!(out[0].is_nan(), "{out:?}");
+    assert!(nan.eval_reverse(&[0.5; 3], None, 1e-4).is_none());
+    assert!(nan.eval_reverse_batch(&[0.5; 6], 1e-4).is_none());
+}
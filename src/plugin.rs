@@ -0,0 +1,54 @@
+//! Plug-ins implemented in Rust.
+//!
+//! Plug-ins are installed per context with [`register`], or by passing [`Plugin::as_mut_ptr`] to `cmsCreateContext`.
+//! LCMS copies what it needs during registration, so the plug-in value doesn't have to outlive the context.
+
+use crate::ffi::*;
+use std::os::raw::c_void;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+pub mod formatters;
+pub mod interpolation;
+pub mod intents;
+pub mod mutex;
+pub mod optimization;
+pub mod parallel;
+pub mod tags;
+pub mod transform;
+
+/// A `#[repr(C)]` plug-in structure that starts with [`PluginBase`].
+///
+/// # Safety
+///
+/// The pointer must point to a valid `PluginBase`, followed by the fields LCMS expects for its `Type`.
+pub unsafe trait Plugin {
+    /// Pointer for `cmsPluginTHR` or `cmsCreateContext`
+    fn as_mut_ptr(&mut self) -> *mut c_void;
+}
+
+/// Installs the plug-in in the context (`cmsPluginTHR`). Returns `false` if LCMS rejected it.
+///
+/// # Safety
+///
+/// `context` must be a valid context, or null for the global context.
+pub unsafe fn register<P: Plugin + ?Sized>(context: Context, plugin: &mut P) -> bool {
+    cmsPluginTHR(context, plugin.as_mut_ptr()) != 0
+}
+
+/// `version` is the first LCMS version that supports this plug-in type.
+pub(crate) fn base(plugin_type: Signature, version: u32) -> PluginBase {
+    PluginBase {
+        Magic: PluginMagicNumber,
+        ExpectedVersion: version,
+        Type: plugin_type,
+        Next: std::ptr::null_mut(),
+    }
+}
+
+/// Unwinding into C is undefined behavior, so callbacks abort instead.
+pub(crate) fn abort_on_panic<R>(f: impl FnOnce() -> R) -> R {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(res) => res,
+        Err(_) => std::process::abort(),
+    }
+}
@@ -0,0 +1,253 @@
+//! Pixel layouts that `PixelFormat` bits can't describe, packed and unpacked by Rust code.
+//!
+//! Pick a `PixelFormat` value that no stock formatter handles (e.g. a 4-byte integer layout),
+//! implement [`Layout`] for it, and register [`Formatters`] in the context.
+//! Transforms created in that context with the custom format will then read and write it directly.
+//!
+//! Only 16-bit transforms are supported. If the other side of the transform is a float format,
+//! LCMS asks for float formatters, and these won't be used.
+//!
+//! LCMS calls formatters for one pixel at a time, so layouts where neighbouring pixels share samples
+//! (e.g. YUV 4:2:2 interleaved) can't be expressed as a [`Layout`]. Implement such a layout
+//! as a whole-line [`Kernel`](super::transform::Kernel) instead.
+
+use super::{abort_on_panic, base, Plugin};
+use crate::ffi::*;
+use std::marker::PhantomData;
+use std::os::raw::c_void;
+use std::slice;
+
+/// Converts between one pixel in memory and 16-bit channel values
+pub trait Layout: 'static {
+    /// The format value to claim. Its color space and channel count are used by LCMS to check the profiles.
+    /// If it's planar, each channel is read from its own plane.
+    const FORMAT: PixelFormat;
+    /// Size of one pixel in the buffer, or of one pixel's sample in one plane for planar formats
+    const BYTES_PER_PIXEL: usize;
+
+    /// `channels` has `FORMAT.channels()` elements
+    fn unpack(pixel: PixelBytes<'_>, channels: &mut [u16]);
+
+    /// `channels` has `FORMAT.channels()` elements
+    fn pack(channels: &[u16], pixel: PixelBytesMut<'_>);
+}
+
+/// Bytes of one pixel, in one plane for chunky formats, or in every plane for planar formats
+pub struct PixelBytes<'a> {
+    ptr: *const u8,
+    len: usize,
+    planes: usize,
+    plane_stride: usize,
+    _buffer: PhantomData<&'a [u8]>,
+}
+
+impl<'a> PixelBytes<'a> {
+    /// `BYTES_PER_PIXEL` bytes of the pixel in the first (or only) plane
+    #[must_use]
+    pub fn bytes(&self) -> &'a [u8] {
+        self.plane(0)
+    }
+
+    /// `BYTES_PER_PIXEL` bytes of the pixel in the given plane
+    ///
+    /// # Panics
+    ///
+    /// If there's no such plane
+    #[must_use]
+    pub fn plane(&self, plane: usize) -> &'a [u8] {
+        assert!(plane < self.planes);
+        unsafe { slice::from_raw_parts(self.ptr.add(plane * self.plane_stride), self.len) }
+    }
+
+    /// Distance in bytes between planes (LCMS's stride argument). 0 for chunky formats.
+    #[must_use]
+    pub fn plane_stride(&self) -> usize {
+        self.plane_stride
+    }
+}
+
+/// Writable [`PixelBytes`]
+pub struct PixelBytesMut<'a> {
+    ptr: *mut u8,
+    len: usize,
+    planes: usize,
+    plane_stride: usize,
+    _buffer: PhantomData<&'a mut [u8]>,
+}
+
+impl PixelBytesMut<'_> {
+    /// `BYTES_PER_PIXEL` bytes of the pixel in the first (or only) plane
+    #[must_use]
+    pub fn bytes(&mut self) -> &mut [u8] {
+        self.plane(0)
+    }
+
+    /// `BYTES_PER_PIXEL` bytes of the pixel in the given plane
+    ///
+    /// # Panics
+    ///
+    /// If there's no such plane
+    #[must_use]
+    pub fn plane(&mut self, plane: usize) -> &mut [u8] {
+        assert!(plane < self.planes);
+        unsafe { slice::from_raw_parts_mut(self.ptr.add(plane * self.plane_stride), self.len) }
+    }
+
+    /// Distance in bytes between planes (LCMS's stride argument). 0 for chunky formats.
+    #[must_use]
+    pub fn plane_stride(&self) -> usize {
+        self.plane_stride
+    }
+}
+
+/// Number of planes and the distance between them
+fn planes<L: Layout>(stride: u32) -> (usize, usize) {
+    if L::FORMAT.planar() {
+        (L::FORMAT.channels() + L::FORMAT.extra(), stride as usize)
+    } else {
+        (1, 0)
+    }
+}
+
+/// `cmsPluginFormatters` for a single custom [`Layout`]. Register it once per layout.
+#[repr(transparent)]
+pub struct Formatters<L> {
+    plugin: PluginFormatters,
+    _layout: PhantomData<fn() -> L>,
+}
+
+impl<L: Layout> Formatters<L> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            plugin: PluginFormatters {
+                base: base(PluginFormattersSig, 2060),
+                FormattersFactory: Some(factory::<L>),
+            },
+            _layout: PhantomData,
+        }
+    }
+}
+
+impl<L: Layout> Default for Formatters<L> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<L> Plugin for Formatters<L> {
+    fn as_mut_ptr(&mut self) -> *mut c_void {
+        (&mut self.plugin as *mut PluginFormatters).cast()
+    }
+}
+
+unsafe extern "C" fn factory<L: Layout>(format: u32, dir: FormatterDirection, flags: u32) -> Formatter {
+    if format != L::FORMAT.0 || flags & CMS_PACK_FLAGS_FLOAT != 0 {
+        return Formatter { Fmt16: None };
+    }
+    match dir {
+        FormatterDirection::Input => Formatter { Fmt16: Some(unpack::<L>) },
+        FormatterDirection::Output => Formatter { Fmt16: Some(pack::<L>) },
+    }
+}
+
+unsafe extern "C" fn unpack<L: Layout>(_: HTRANSFORM, values: *mut u16, buffer: *mut u8, stride: u32) -> *mut u8 {
+    let (planes, plane_stride) = planes::<L>(stride);
+    let pixel = PixelBytes { ptr: buffer, len: L::BYTES_PER_PIXEL, planes, plane_stride, _buffer: PhantomData };
+    let channels = slice::from_raw_parts_mut(values, L::FORMAT.channels());
+    abort_on_panic(|| L::unpack(pixel, channels));
+    buffer.add(L::BYTES_PER_PIXEL)
+}
+
+unsafe extern "C" fn pack<L: Layout>(_: HTRANSFORM, values: *mut u16, buffer: *mut u8, stride: u32) -> *mut u8 {
+    let channels = slice::from_raw_parts(values, L::FORMAT.channels());
+    let (planes, plane_stride) = planes::<L>(stride);
+    let pixel = PixelBytesMut { ptr: buffer, len: L::BYTES_PER_PIXEL, planes, plane_stride, _buffer: PhantomData };
+    abort_on_panic(|| L::pack(channels, pixel));
+    buffer.add(L::BYTES_PER_PIXEL)
+}
+
+#[test]
+fn rgb10_a2() {
+    /// 10 bits per channel packed in a little-endian u32, 2 bits of alpha at the top
+    struct Rgb10A2;
+    impl Layout for Rgb10A2 {
+        const FORMAT: PixelFormat = PixelFormat(COLORSPACE_SH(PT_RGB) | CHANNELS_SH(3) | BYTES_SH(4));
+        const BYTES_PER_PIXEL: usize = 4;
+
+        fn unpack(pixel: PixelBytes<'_>, channels: &mut [u16]) {
+            let pixel = pixel.bytes();
+            let p = u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+            for (i, ch) in channels.iter_mut().enumerate() {
+                let v = (p >> (i * 10)) & 1023;
+                *ch = ((v << 6) | (v >> 4)) as u16;
+            }
+        }
+
+        fn pack(channels: &[u16], mut pixel: PixelBytesMut<'_>) {
+            let p = channels.iter().enumerate()
+                .fold(3 << 30, |p, (i, &ch)| p | (u32::from(ch) >> 6) << (i * 10));
+            pixel.bytes().copy_from_slice(&p.to_le_bytes());
+        }
+    }
+
+    /// 12 bits in little-endian u16, one plane per channel
+    struct Rgb12Planar;
+    impl Layout for Rgb12Planar {
+        // Takes over RGB_16_PLANAR in its context
+        const FORMAT: PixelFormat = PixelFormat(COLORSPACE_SH(PT_RGB) | PLANAR_SH(1) | CHANNELS_SH(3) | BYTES_SH(2));
+        const BYTES_PER_PIXEL: usize = 2;
+
+        fn unpack(pixel: PixelBytes<'_>, channels: &mut [u16]) {
+            for (i, ch) in channels.iter_mut().enumerate() {
+                let v = u16::from_le_bytes([pixel.plane(i)[0], pixel.plane(i)[1]]) & 4095;
+                *ch = (v << 4) | (v >> 8);
+            }
+        }
+
+        fn pack(channels: &[u16], mut pixel: PixelBytesMut<'_>) {
+            for (i, &ch) in channels.iter().enumerate() {
+                pixel.plane(i).copy_from_slice(&(ch >> 4).to_le_bytes());
+            }
+        }
+    }
+
+    unsafe {
+        let context = cmsCreateContext(Formatters::<Rgb10A2>::new().as_mut_ptr(), std::ptr::null_mut());
+        let profile = cmsCreate_sRGBProfileTHR(context);
+        let to16 = cmsCreateTransformTHR(context, profile, Rgb10A2::FORMAT, profile, PixelFormat::RGB_16, Intent::Perceptual, 0);
+        let from16 = cmsCreateTransformTHR(context, profile, PixelFormat::RGB_16, profile, Rgb10A2::FORMAT, Intent::Perceptual, 0);
+        assert!(!to16.is_null() && !from16.is_null());
+
+        let input = [0xC000_0000u32 | (1023 << 20) | (512 << 10), 0xC000_0000 | 1023];
+        let mut rgb16 = [0u16; 6];
+        cmsDoTransform(to16, input.as_ptr().cast(), rgb16.as_mut_ptr().cast(), 2);
+        for (&actual, expected) in rgb16.iter().zip([0, 0x8020, 0xFFFF, 0xFFFF, 0, 0]) {
+            assert!((i32::from(actual) - expected).abs() <= 64, "{rgb16:?}");
+        }
+
+        let mut roundtrip = [0u32; 2];
+        cmsDoTransform(from16, rgb16.as_ptr().cast(), roundtrip.as_mut_ptr().cast(), 2);
+        assert_eq!(input, roundtrip);
+
+        cmsDeleteTransform(to16);
+        cmsDeleteTransform(from16);
+        cmsCloseProfile(profile);
+        cmsDeleteContext(context);
+
+        let context = cmsCreateContext(Formatters::<Rgb12Planar>::new().as_mut_ptr(), std::ptr::null_mut());
+        let profile = cmsCreate_sRGBProfileTHR(context);
+        let swap = cmsCreateTransformTHR(context, profile, Rgb12Planar::FORMAT, profile, PixelFormat::BGR_16, Intent::Perceptual, 0);
+        assert!(!swap.is_null());
+        // Planes of R, G and B for 2 pixels
+        let planar = [4095u16, 0, 2048, 0, 0, 4095];
+        let mut bgr = [0u16; 6];
+        cmsDoTransform(swap, planar.as_ptr().cast(), bgr.as_mut_ptr().cast(), 2);
+        for (&actual, expected) in bgr.iter().zip([0, 0x8008, 0xFFFF, 0xFFFF, 0, 0]) {
+            assert!((i32::from(actual) - expected).abs() <= 64, "{bgr:?}");
+        }
+        cmsDeleteTransform(swap);
+        cmsCloseProfile(profile);
+        cmsDeleteContext(context);
+    }
+}
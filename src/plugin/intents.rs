@@ -0,0 +1,202 @@
+//! Rendering intents implemented in Rust.
+//!
+//! `Intent` only lists the built-in intents, so custom intents are identified by their [`IntentCode`].
+//! Safe constructors such as [`Transform::new`](crate::transform::Transform::new) take codes,
+//! and so do [`create_transform`] and `cmsCreateExtendedTransform`.
+//! Registered intents are listed by `cmsGetSupportedIntentsTHR`.
+
+use super::{abort_on_panic, base, Plugin};
+use crate::ffi::*;
+use std::marker::PhantomData;
+use std::os::raw::{c_char, c_void};
+use std::slice;
+
+/// Profiles and settings of a transform being created with a custom intent
+pub struct Chain<'a> {
+    context: Context,
+    profiles: &'a [HPROFILE],
+    intents: &'a [IntentCode],
+    bpc: &'a [Bool],
+    adaptation_states: &'a [f64],
+    flags: u32,
+}
+
+impl Chain<'_> {
+    #[must_use]
+    pub fn context(&self) -> Context {
+        self.context
+    }
+
+    /// All profiles, from input to output
+    #[must_use]
+    pub fn profiles(&self) -> &[HPROFILE] {
+        self.profiles
+    }
+
+    /// Intent code for each profile
+    #[must_use]
+    pub fn intents(&self) -> &[IntentCode] {
+        self.intents
+    }
+
+    /// Black point compensation for each profile
+    #[must_use]
+    pub fn bpc(&self) -> &[Bool] {
+        self.bpc
+    }
+
+    #[must_use]
+    pub fn adaptation_states(&self) -> &[f64] {
+        self.adaptation_states
+    }
+
+    /// `FLAGS_*`
+    #[must_use]
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// Links the profiles the way LCMS would for a built-in `intent` (`_cmsDefaultICCintents`).
+    ///
+    /// Useful as a base to add stages to. Returns null on failure. The caller owns the pipeline.
+    #[must_use]
+    pub fn default_pipeline(&self, intent: Intent) -> *mut Pipeline {
+        let mut intents = vec![intent as u32; self.profiles.len()];
+        let mut profiles = self.profiles.to_vec();
+        let mut bpc = self.bpc.to_vec();
+        let mut adaptation_states = self.adaptation_states.to_vec();
+        unsafe {
+            _cmsDefaultICCintents(self.context, profiles.len() as u32, intents.as_mut_ptr(), profiles.as_mut_ptr(),
+                bpc.as_mut_ptr(), adaptation_states.as_mut_ptr(), self.flags)
+        }
+    }
+}
+
+pub trait CustomIntent: 'static {
+    /// Must not clash with built-in `Intent` values
+    const CODE: IntentCode;
+    /// Shown by `cmsGetSupportedIntentsTHR`. Truncated to 255 bytes.
+    const DESCRIPTION: &'static str;
+
+    /// Joins all profiles of the chain into a single pipeline, and returns it (or null on failure).
+    fn link(chain: &Chain<'_>) -> *mut Pipeline;
+}
+
+/// `cmsPluginRenderingIntent` for `I`
+#[repr(transparent)]
+pub struct RenderingIntent<I> {
+    plugin: PluginRenderingIntent,
+    _intent: PhantomData<fn() -> I>,
+}
+
+impl<I: CustomIntent> RenderingIntent<I> {
+    #[must_use]
+    pub fn new() -> Self {
+        let mut description = [0 as c_char; 256];
+        for (d, &s) in description[..255].iter_mut().zip(I::DESCRIPTION.as_bytes()) {
+            *d = s as c_char;
+        }
+        Self {
+            plugin: PluginRenderingIntent {
+                base: base(PluginRenderingIntentSig, 2060),
+                Intent: I::CODE.0,
+                Link: Some(link::<I>),
+                Description: description,
+            },
+            _intent: PhantomData,
+        }
+    }
+}
+
+impl<I: CustomIntent> Default for RenderingIntent<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<I> Plugin for RenderingIntent<I> {
+    fn as_mut_ptr(&mut self) -> *mut c_void {
+        (&mut self.plugin as *mut PluginRenderingIntent).cast()
+    }
+}
+
+unsafe extern "C" fn link<I: CustomIntent>(context: Context, n: u32, intents: *mut u32, profiles: *mut HPROFILE, bpc: *mut Bool, adaptation_states: *mut f64, flags: u32) -> *mut Pipeline {
+    let n = n as usize;
+    let chain = Chain {
+        context,
+        profiles: slice::from_raw_parts(profiles, n),
+        // `IntentCode` is a transparent `u32`
+        intents: slice::from_raw_parts(intents.cast::<IntentCode>(), n),
+        bpc: slice::from_raw_parts(bpc, n),
+        adaptation_states: slice::from_raw_parts(adaptation_states, n),
+        flags,
+    };
+    abort_on_panic(|| I::link(&chain))
+}
+
+/// Same as `cmsCreateTransformTHR`, but takes any intent code, including ones from plug-ins
+///
+/// The output profile can be null, e.g. for device links or named colour profiles.
+///
+/// # Safety
+///
+/// Context and profiles must be valid.
+pub unsafe fn create_transform(context: Context, input: HPROFILE, input_format: PixelFormat, output: HPROFILE, output_format: PixelFormat,
+    intent: impl Into<IntentCode>, flags: u32) -> HTRANSFORM {
+    let mut profiles = [input, output];
+    let count = if output.is_null() { 1 } else { 2 };
+    let mut bpc = [Bool::from(flags & FLAGS_BLACKPOINTCOMPENSATION != 0); 2];
+    let mut intents = [intent.into().0; 2];
+    // Negative value only reads current state
+    let mut adaptation_states = [cmsSetAdaptationStateTHR(context, -1.); 2];
+    cmsCreateExtendedTransform(context, count, profiles.as_mut_ptr(), bpc.as_mut_ptr(), intents.as_mut_ptr(),
+        adaptation_states.as_mut_ptr(), std::ptr::null_mut(), 0, input_format, output_format, flags)
+}
+
+#[test]
+fn custom_intent() {
+    use std::ffi::CStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static LINKED: AtomicUsize = AtomicUsize::new(0);
+    struct Compress;
+    impl CustomIntent for Compress {
+        const CODE: IntentCode = IntentCode(0x1234);
+        const DESCRIPTION: &'static str = "Gamut compression";
+        fn link(chain: &Chain<'_>) -> *mut Pipeline {
+            assert_eq!(&[Self::CODE; 2], chain.intents());
+            LINKED.fetch_add(1, Ordering::SeqCst);
+            chain.default_pipeline(Intent::Perceptual)
+        }
+    }
+
+    unsafe {
+        let context = cmsCreateContext(RenderingIntent::<Compress>::new().as_mut_ptr(), std::ptr::null_mut());
+        let mut codes = [0u32; 32];
+        let mut descriptions = [std::ptr::null_mut::<c_char>(); 32];
+        let n = cmsGetSupportedIntentsTHR(context, 32, codes.as_mut_ptr(), descriptions.as_mut_ptr()) as usize;
+        let i = codes[..n].iter().position(|&c| c == Compress::CODE.0).unwrap();
+        assert_eq!(None, IntentCode(codes[i]).intent());
+        assert_eq!(b"Gamut compression", CStr::from_ptr(descriptions[i]).to_bytes());
+
+        let profile = cmsCreate_sRGBProfileTHR(context);
+        let xform = create_transform(context, profile, PixelFormat::RGB_8, profile, PixelFormat::RGB_8, Compress::CODE, 0);
+        assert!(!xform.is_null());
+        assert_eq!(1, LINKED.load(Ordering::SeqCst));
+        let mut out = [0u8; 3];
+        cmsDoTransform(xform, [255u8, 0, 0].as_ptr().cast(), out.as_mut_ptr().cast(), 1);
+        assert!(out[0] > 250 && out[1] < 5 && out[2] < 5, "{out:?}");
+
+        cmsDeleteTransform(xform);
+
+        let xform = crate::transform::Transform::<[u8; 3], [u8; 3]>::new_thr(context, profile, profile, Compress::CODE, Default::default()).unwrap();
+        assert_eq!(2, LINKED.load(Ordering::SeqCst));
+        let mut out = [[0u8; 3]];
+        xform.transform(&[[255, 0, 0]], &mut out);
+        assert!(out[0][0] > 250 && out[0][1] < 5, "{out:?}");
+        assert_eq!(Compress::CODE, xform.info().unwrap().intent);
+        drop(xform);
+        cmsCloseProfile(profile);
+        cmsDeleteContext(context);
+    }
+}
@@ -0,0 +1,186 @@
+//! Interpolation routines implemented in Rust.
+//!
+//! LCMS asks registered interpolators first whenever it needs to interpolate a table: CLUT stages
+//! (`cmsStageAllocCLut16bit`, `cmsStageAllocCLutFloat`) and tabulated tone curves.
+//! An [`Interpolator`] that doesn't support a combination of channels leaves it to LCMS.
+
+use super::{abort_on_panic, base, Plugin};
+use crate::ffi::*;
+use std::marker::PhantomData;
+use std::os::raw::c_void;
+use std::slice;
+
+pub trait Interpolator: 'static {
+    /// Whether to interpolate tables with these channel counts. `flags` are `CMS_LERP_FLAGS_*`.
+    ///
+    /// If `flags` has `CMS_LERP_FLAGS_FLOAT`, [`Interpolator::interpolate_float`] is used, otherwise [`Interpolator::interpolate16`].
+    fn supports(inputs: usize, outputs: usize, flags: u32) -> bool;
+
+    /// `input` has `table.inputs()` elements, and `output` has `table.outputs()` elements
+    fn interpolate16(table: &Table<'_, u16>, input: &[u16], output: &mut [u16]);
+
+    /// Inputs are in 0..=1 range. `input` has `table.inputs()` elements, and `output` has `table.outputs()` elements
+    fn interpolate_float(table: &Table<'_, f32>, input: &[f32], output: &mut [f32]);
+}
+
+/// Regularly sampled table being interpolated (`cmsInterpParams`)
+pub struct Table<'a, T> {
+    params: &'a InterpParams,
+    _values: PhantomData<&'a [T]>,
+}
+
+impl<'a, T> Table<'a, T> {
+    #[must_use]
+    pub fn params(&self) -> &'a InterpParams {
+        self.params
+    }
+
+    #[must_use]
+    pub fn inputs(&self) -> usize {
+        self.params.nInputs as usize
+    }
+
+    #[must_use]
+    pub fn outputs(&self) -> usize {
+        self.params.nOutputs as usize
+    }
+
+    /// Number of grid points for each input
+    #[must_use]
+    pub fn samples(&self) -> &'a [u32] {
+        &self.params.nSamples[..self.inputs()]
+    }
+
+    /// `CMS_LERP_FLAGS_*` the interpolator was created with
+    #[must_use]
+    pub fn flags(&self) -> u32 {
+        self.params.dwFlags
+    }
+
+    /// All nodes. The first input varies slowest, and each node has `outputs()` values.
+    #[must_use]
+    pub fn values(&self) -> &'a [T] {
+        let len = self.samples().iter().map(|&n| n as usize).product::<usize>() * self.outputs();
+        unsafe { slice::from_raw_parts(self.params.Table.cast::<T>(), len) }
+    }
+
+    /// Output values of the grid point at `coords` (one index per input)
+    #[must_use]
+    pub fn node(&self, coords: &[usize]) -> &'a [T] {
+        assert_eq!(coords.len(), self.inputs());
+        let start = coords.iter().rev().zip(&self.params.opta).map(|(&c, &o)| c * o as usize).sum::<usize>();
+        &self.values()[start..start + self.outputs()]
+    }
+}
+
+/// `cmsPluginInterpolation` for `I`
+#[repr(transparent)]
+pub struct Interpolation<I> {
+    plugin: PluginInterpolation,
+    _interpolator: PhantomData<fn() -> I>,
+}
+
+impl<I: Interpolator> Interpolation<I> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            plugin: PluginInterpolation {
+                base: base(PluginInterpolationSig, 2060),
+                InterpolatorsFactory: Some(factory::<I>),
+            },
+            _interpolator: PhantomData,
+        }
+    }
+}
+
+impl<I: Interpolator> Default for Interpolation<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<I> Plugin for Interpolation<I> {
+    fn as_mut_ptr(&mut self) -> *mut c_void {
+        (&mut self.plugin as *mut PluginInterpolation).cast()
+    }
+}
+
+unsafe extern "C" fn factory<I: Interpolator>(inputs: u32, outputs: u32, flags: u32) -> InterpFunction {
+    if !abort_on_panic(|| I::supports(inputs as usize, outputs as usize, flags)) {
+        return InterpFunction { Lerp16: None };
+    }
+    if flags & CMS_LERP_FLAGS_FLOAT != 0 {
+        InterpFunction { LerpFloat: Some(lerp_float::<I>) }
+    } else {
+        InterpFunction { Lerp16: Some(lerp16::<I>) }
+    }
+}
+
+unsafe extern "C" fn lerp16<I: Interpolator>(input: *const u16, output: *mut u16, params: *const InterpParams) {
+    let table = Table { params: &*params, _values: PhantomData };
+    let input = slice::from_raw_parts(input, table.inputs());
+    let output = slice::from_raw_parts_mut(output, table.outputs());
+    abort_on_panic(|| I::interpolate16(&table, input, output));
+}
+
+unsafe extern "C" fn lerp_float<I: Interpolator>(input: *const f32, output: *mut f32, params: *const InterpParams) {
+    let table = Table { params: &*params, _values: PhantomData };
+    let input = slice::from_raw_parts(input, table.inputs());
+    let output = slice::from_raw_parts_mut(output, table.outputs());
+    abort_on_panic(|| I::interpolate_float(&table, input, output));
+}
+
+#[test]
+fn nearest_neighbor() {
+    struct Nearest;
+    impl Interpolator for Nearest {
+        fn supports(inputs: usize, _: usize, _: u32) -> bool {
+            inputs == 3
+        }
+
+        fn interpolate16(table: &Table<'_, u16>, input: &[u16], output: &mut [u16]) {
+            let coords: Vec<_> = input.iter().zip(table.samples())
+                .map(|(&i, &n)| ((u32::from(i) * (n - 1) + 0x7FFF) / 0xFFFF) as usize)
+                .collect();
+            output.copy_from_slice(table.node(&coords));
+        }
+
+        fn interpolate_float(table: &Table<'_, f32>, input: &[f32], output: &mut [f32]) {
+            let coords: Vec<_> = input.iter().zip(table.samples())
+                .map(|(&i, &n)| (i.clamp(0., 1.) * (n - 1) as f32).round() as usize)
+                .collect();
+            output.copy_from_slice(table.node(&coords));
+        }
+    }
+
+    // 2×2×2 grid with 3 outputs, where each node stores its own coordinates
+    let mut grid = Vec::new();
+    for r in 0..2u16 {
+        for g in 0..2u16 {
+            for b in 0..2u16 {
+                grid.extend([r * 0xFFFF, g * 0xFFFF, b * 0xFFFF]);
+            }
+        }
+    }
+    let grid_float: Vec<f32> = grid.iter().map(|&v| f32::from(v) / 65535.).collect();
+
+    unsafe {
+        let context = cmsCreateContext(Interpolation::<Nearest>::new().as_mut_ptr(), std::ptr::null_mut());
+        let lut = cmsPipelineAlloc(context, 3, 3);
+        cmsPipelineInsertStage(lut, StageLoc::AT_END, cmsStageAllocCLut16bit(context, 2, 3, 3, grid.as_ptr()));
+        let mut out = [0u16; 3];
+        cmsPipelineEval16([0x7000, 0x9000, 0xFFFF].as_ptr(), out.as_mut_ptr(), lut);
+        // Trilinear would give [0x7000, 0x9000, 0xFFFF]
+        assert_eq!([0, 0xFFFF, 0xFFFF], out);
+        cmsPipelineFree(lut);
+
+        let lut = cmsPipelineAlloc(context, 3, 3);
+        cmsPipelineInsertStage(lut, StageLoc::AT_END, cmsStageAllocCLutFloat(context, 2, 3, 3, grid_float.as_ptr()));
+        let mut out = [0f32; 3];
+        cmsPipelineEvalFloat([0.9, 0.1, 0.4].as_ptr(), out.as_mut_ptr(), lut);
+        assert_eq!([1., 0., 0.], out);
+        cmsPipelineFree(lut);
+
+        cmsDeleteContext(context);
+    }
+}
@@ -0,0 +1,149 @@
+//! Locks that LCMS uses internally (e.g. for profile tag caches), implemented in Rust.
+
+use super::{abort_on_panic, base, Plugin};
+use crate::ffi::*;
+use std::marker::PhantomData;
+use std::os::raw::c_void;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex};
+
+/// A lock that isn't tied to a scope. LCMS calls `lock` and `unlock` separately.
+pub trait RawLock: Send + Sync + 'static {
+    fn new() -> Self;
+
+    /// Blocks until the lock is acquired
+    fn lock(&self);
+
+    /// # Safety
+    ///
+    /// Must be called only when locked by `lock`.
+    unsafe fn unlock(&self);
+}
+
+/// Lock made of `std::sync::Mutex` and `Condvar`, which counts how many times a thread had to wait for it
+#[derive(Default)]
+pub struct StdLock {
+    locked: Mutex<bool>,
+    released: Condvar,
+}
+
+static CONTENDED: AtomicU64 = AtomicU64::new(0);
+
+impl StdLock {
+    /// Number of times any `StdLock` was already locked when a thread tried to lock it
+    #[must_use]
+    pub fn contended_count() -> u64 {
+        CONTENDED.load(Ordering::Relaxed)
+    }
+}
+
+impl RawLock for StdLock {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) {
+        // The bool is always left consistent, so poisoning can be ignored
+        let mut locked = self.locked.lock().unwrap_or_else(|e| e.into_inner());
+        if *locked {
+            CONTENDED.fetch_add(1, Ordering::Relaxed);
+            while *locked {
+                locked = self.released.wait(locked).unwrap_or_else(|e| e.into_inner());
+            }
+        }
+        *locked = true;
+    }
+
+    unsafe fn unlock(&self) {
+        *self.locked.lock().unwrap_or_else(|e| e.into_inner()) = false;
+        self.released.notify_one();
+    }
+}
+
+/// `cmsPluginMutex` that creates an `L` for every lock LCMS needs
+#[repr(transparent)]
+pub struct MutexPlugin<L = StdLock> {
+    plugin: PluginMutex,
+    _lock: PhantomData<fn() -> L>,
+}
+
+impl<L: RawLock> MutexPlugin<L> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            plugin: PluginMutex {
+                base: base(PluginMutexSig, 2060),
+                CreateMutexPtr: Some(create::<L>),
+                DestroyMutexPtr: Some(destroy::<L>),
+                LockMutexPtr: Some(lock::<L>),
+                UnlockMutexPtr: Some(unlock::<L>),
+            },
+            _lock: PhantomData,
+        }
+    }
+}
+
+impl<L: RawLock> Default for MutexPlugin<L> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<L> Plugin for MutexPlugin<L> {
+    fn as_mut_ptr(&mut self) -> *mut c_void {
+        (&mut self.plugin as *mut PluginMutex).cast()
+    }
+}
+
+unsafe extern "C" fn create<L: RawLock>(_: Context) -> *mut c_void {
+    abort_on_panic(|| Box::into_raw(Box::new(L::new())).cast())
+}
+
+unsafe extern "C" fn destroy<L: RawLock>(_: Context, mtx: *mut c_void) {
+    if !mtx.is_null() {
+        abort_on_panic(|| drop(Box::from_raw(mtx.cast::<L>())));
+    }
+}
+
+unsafe extern "C" fn lock<L: RawLock>(_: Context, mtx: *mut c_void) -> Bool {
+    if let Some(mtx) = mtx.cast::<L>().as_ref() {
+        abort_on_panic(|| mtx.lock());
+    }
+    1
+}
+
+unsafe extern "C" fn unlock<L: RawLock>(_: Context, mtx: *mut c_void) {
+    if let Some(mtx) = mtx.cast::<L>().as_ref() {
+        abort_on_panic(|| mtx.unlock());
+    }
+}
+
+#[test]
+fn locks_profile_tags() {
+    use std::sync::atomic::AtomicIsize;
+
+    static LIVE: AtomicIsize = AtomicIsize::new(0);
+    static HELD: AtomicIsize = AtomicIsize::new(0);
+    static LOCK_CALLS: AtomicIsize = AtomicIsize::new(0);
+    struct Counted(StdLock);
+    impl RawLock for Counted {
+        fn new() -> Self { LIVE.fetch_add(1, Ordering::SeqCst); Self(StdLock::new()) }
+        fn lock(&self) { LOCK_CALLS.fetch_add(1, Ordering::SeqCst); HELD.fetch_add(1, Ordering::SeqCst); self.0.lock(); }
+        unsafe fn unlock(&self) { HELD.fetch_sub(1, Ordering::SeqCst); self.0.unlock(); }
+    }
+    impl Drop for Counted {
+        fn drop(&mut self) { LIVE.fetch_sub(1, Ordering::SeqCst); }
+    }
+
+    unsafe {
+        let context = cmsCreateContext(MutexPlugin::<Counted>::new().as_mut_ptr(), std::ptr::null_mut());
+        let profile = cmsCreate_sRGBProfileTHR(context);
+        assert!(LIVE.load(Ordering::SeqCst) > 0);
+        assert!(!cmsReadTag(profile, TagSignature::RedColorantTag).is_null());
+        assert!(LOCK_CALLS.load(Ordering::SeqCst) > 0);
+        assert_eq!(0, HELD.load(Ordering::SeqCst));
+        cmsCloseProfile(profile);
+        cmsDeleteContext(context);
+        assert_eq!(0, LIVE.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,208 @@
+//! Pipeline optimizations implemented in Rust.
+//!
+//! When a transform is created, LCMS first simplifies its pipeline, and then offers it to each registered
+//! [`Optimizer`], before trying built-in optimizations. An optimizer can look at the stages,
+//! and take over evaluation of the whole pipeline with a specialized [`Evaluator16`].
+
+use super::{abort_on_panic, base, Plugin};
+use crate::ffi::*;
+use crate::pipeline::{StageRef, Stages};
+use std::marker::PhantomData;
+use std::os::raw::c_void;
+use std::slice;
+
+/// Evaluates a whole pipeline in 16 bits. It's cloned when the pipeline is duplicated.
+pub trait Evaluator16: Clone + Send + Sync + 'static {
+    /// Slices have as many elements as the pipeline has input and output channels
+    fn eval(&self, input: &[u16], output: &mut [u16]);
+}
+
+pub trait Optimizer: 'static {
+    /// Returns `true` if it has optimized the pipeline, which stops the search for other optimizations
+    fn optimize(candidate: &mut Candidate<'_>) -> bool;
+}
+
+/// Pipeline of a transform being created
+pub struct Candidate<'a> {
+    lut: &'a mut *mut Pipeline,
+    intent: u32,
+    input_format: &'a mut u32,
+    output_format: &'a mut u32,
+    flags: &'a mut u32,
+}
+
+impl Candidate<'_> {
+    #[must_use]
+    pub fn pipeline(&self) -> *mut Pipeline {
+        *self.lut
+    }
+
+    /// Intent, which may be a custom one
+    #[must_use]
+    pub fn intent(&self) -> IntentCode {
+        IntentCode(self.intent)
+    }
+
+    #[must_use]
+    pub fn input_format(&self) -> PixelFormat {
+        PixelFormat(*self.input_format)
+    }
+
+    #[must_use]
+    pub fn output_format(&self) -> PixelFormat {
+        PixelFormat(*self.output_format)
+    }
+
+    /// `FLAGS_*` of the transform
+    #[must_use]
+    pub fn flags(&self) -> u32 {
+        *self.flags
+    }
+
+    /// Stages from first to last
+    #[must_use]
+    pub fn stages(&self) -> Vec<StageRef<'_>> {
+        // Borrowed from `self`, so the pipeline can't be replaced meanwhile
+        unsafe { Stages::from_pipeline(*self.lut) }.collect()
+    }
+
+    /// [`StageRef::stage_type`] of every stage, from first to last
+    #[must_use]
+    pub fn stage_types(&self) -> Vec<Signature> {
+        self.stages().iter().map(StageRef::stage_type).collect()
+    }
+
+    /// Evaluate the pipeline with `evaluator` instead of its stages
+    pub fn set_evaluator<E: Evaluator16>(&mut self, evaluator: E) {
+        unsafe {
+            let data = Box::new(Private {
+                evaluator,
+                inputs: cmsPipelineInputChannels(*self.lut) as usize,
+                outputs: cmsPipelineOutputChannels(*self.lut) as usize,
+            });
+            _cmsPipelineSetOptimizationParameters(*self.lut, Some(eval::<E>), Box::into_raw(data).cast(), Some(free::<E>), Some(dup::<E>));
+        }
+    }
+
+    /// Replaces the pipeline, and frees the old one
+    ///
+    /// # Safety
+    ///
+    /// `pipeline` must be a valid pipeline with the same number of channels, and nothing else may own it.
+    pub unsafe fn replace(&mut self, pipeline: *mut Pipeline) {
+        cmsPipelineFree(*self.lut);
+        *self.lut = pipeline;
+    }
+}
+
+struct Private<E> {
+    evaluator: E,
+    inputs: usize,
+    outputs: usize,
+}
+
+unsafe extern "C" fn eval<E: Evaluator16>(input: *const u16, output: *mut u16, data: *const c_void) {
+    let data = &*data.cast::<Private<E>>();
+    let input = slice::from_raw_parts(input, data.inputs);
+    let output = slice::from_raw_parts_mut(output, data.outputs);
+    abort_on_panic(|| data.evaluator.eval(input, output));
+}
+
+unsafe extern "C" fn free<E: Evaluator16>(_: Context, data: *mut c_void) {
+    if !data.is_null() {
+        abort_on_panic(|| drop(Box::from_raw(data.cast::<Private<E>>())));
+    }
+}
+
+unsafe extern "C" fn dup<E: Evaluator16>(_: Context, data: *const c_void) -> *mut c_void {
+    let data = &*data.cast::<Private<E>>();
+    abort_on_panic(|| Box::into_raw(Box::new(Private {
+        evaluator: data.evaluator.clone(),
+        ..*data
+    })).cast())
+}
+
+/// `cmsPluginOptimization` for `O`
+#[repr(transparent)]
+pub struct Optimization<O> {
+    plugin: PluginOptimization,
+    _optimizer: PhantomData<fn() -> O>,
+}
+
+impl<O: Optimizer> Optimization<O> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            plugin: PluginOptimization {
+                base: base(PluginOptimizationSig, 2060),
+                OptimizePtr: Some(optimize::<O>),
+            },
+            _optimizer: PhantomData,
+        }
+    }
+}
+
+impl<O: Optimizer> Default for Optimization<O> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<O> Plugin for Optimization<O> {
+    fn as_mut_ptr(&mut self) -> *mut c_void {
+        (&mut self.plugin as *mut PluginOptimization).cast()
+    }
+}
+
+unsafe extern "C" fn optimize<O: Optimizer>(lut: *mut *mut Pipeline, intent: u32, input_format: *mut u32, output_format: *mut u32, flags: *mut u32) -> Bool {
+    let mut candidate = Candidate {
+        lut: &mut *lut,
+        intent,
+        input_format: &mut *input_format,
+        output_format: &mut *output_format,
+        flags: &mut *flags,
+    };
+    abort_on_panic(|| O::optimize(&mut candidate)).into()
+}
+
+#[test]
+fn replaces_evaluator() {
+    #[derive(Clone)]
+    struct Constant;
+    impl Evaluator16 for Constant {
+        fn eval(&self, input: &[u16], output: &mut [u16]) {
+            assert_eq!(3, input.len());
+            output.copy_from_slice(&[1, 2, 3]);
+        }
+    }
+
+    struct OnlyMatrixShaper;
+    impl Optimizer for OnlyMatrixShaper {
+        fn optimize(candidate: &mut Candidate<'_>) -> bool {
+            if !candidate.stage_types().contains(&(StageSignature::MatrixElemType as Signature)) {
+                return false;
+            }
+            assert_eq!(PixelFormat::RGB_16, candidate.input_format());
+            assert_eq!(Some(Intent::Perceptual), candidate.intent().intent());
+            assert!(candidate.stages().iter().any(|s| s.matrix().is_some()));
+            candidate.set_evaluator(Constant);
+            true
+        }
+    }
+
+    unsafe {
+        let context = cmsCreateContext(Optimization::<OnlyMatrixShaper>::new().as_mut_ptr(), std::ptr::null_mut());
+        let rgb = cmsCreate_sRGBProfileTHR(context);
+        let xyz = cmsCreateXYZProfileTHR(context);
+        let xform = cmsCreateTransformTHR(context, rgb, PixelFormat::RGB_16, xyz, PixelFormat::XYZ_16, Intent::Perceptual, FLAGS_NOCACHE);
+        assert!(!xform.is_null());
+        let mut out = [0u16; 6];
+        cmsDoTransform(xform, [0u16, 1000, 65535, 5, 5, 5].as_ptr().cast(), out.as_mut_ptr().cast(), 2);
+        assert_eq!([1, 2, 3, 1, 2, 3], out);
+
+        cmsDeleteTransform(xform);
+        cmsCloseProfile(rgb);
+        cmsCloseProfile(xyz);
+        cmsDeleteContext(context);
+    }
+}
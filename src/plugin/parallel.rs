@@ -0,0 +1,214 @@
+//! Runs transforms in parallel on an executor chosen by the application.
+//!
+//! Every `HTRANSFORM` created in a context with [`Parallelization`] registered splits its work into stripes,
+//! and hands them to the [`Executor`], so LCMS never needs to spawn threads of its own.
+
+use super::{abort_on_panic, base, Plugin};
+use crate::ffi::*;
+use std::marker::PhantomData;
+use std::os::raw::c_void;
+
+/// Stripes smaller than this aren't worth sending to another thread
+const MIN_PIXELS_PER_JOB: usize = 1024;
+
+/// One stripe of a transform
+pub type Job<'a> = Box<dyn FnOnce() + Send + 'a>;
+
+/// Thread pool or task scheduler that runs the stripes.
+///
+/// This is a type, not a value, because LCMS calls the scheduler without any user data.
+/// Implement it on a marker type that reaches your pool through a `static`.
+///
+/// # Safety
+///
+/// `run` must not return until every job has finished, because the jobs borrow the caller's pixel buffers.
+pub unsafe trait Executor {
+    /// How many stripes to split a transform call into, unless [`Parallelization::max_workers`] is set
+    #[must_use]
+    fn max_workers() -> usize {
+        std::thread::available_parallelism().map_or(1, |n| n.get())
+    }
+
+    /// Runs all jobs, in any order, and waits for them
+    fn run(jobs: Vec<Job<'_>>);
+}
+
+/// Runs stripes on the current rayon thread pool (the global one, or the one from `ThreadPool::install`)
+#[cfg(feature = "rayon")]
+pub struct Rayon;
+
+#[cfg(feature = "rayon")]
+unsafe impl Executor for Rayon {
+    fn max_workers() -> usize {
+        rayon::current_num_threads()
+    }
+
+    fn run(jobs: Vec<Job<'_>>) {
+        rayon::scope(move |s| {
+            for job in jobs {
+                s.spawn(move |_| job());
+            }
+        });
+    }
+}
+
+/// `cmsPluginParalellization` that schedules work on `E`
+#[repr(transparent)]
+pub struct Parallelization<E> {
+    plugin: PluginParalellization,
+    _executor: PhantomData<fn() -> E>,
+}
+
+impl<E: Executor> Parallelization<E> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            plugin: PluginParalellization {
+                base: base(PluginParalellizationSig, 2140),
+                MaxWorkers: CMS_GUESS_MAX_WORKERS,
+                WorkerFlags: 0,
+                SchedulerFn: Some(scheduler::<E>),
+            },
+            _executor: PhantomData,
+        }
+    }
+
+    /// Limit number of stripes per transform call, instead of asking [`Executor::max_workers`]
+    #[must_use]
+    pub fn max_workers(mut self, max_workers: u32) -> Self {
+        self.plugin.MaxWorkers = max_workers.min(i32::MAX as u32) as i32;
+        self
+    }
+}
+
+impl<E: Executor> Default for Parallelization<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<E> Plugin for Parallelization<E> {
+    fn as_mut_ptr(&mut self) -> *mut c_void {
+        (&mut self.plugin as *mut PluginParalellization).cast()
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Stripe {
+    cargo: HTRANSFORM,
+    input: *const c_void,
+    output: *mut c_void,
+    pixels_per_line: u32,
+    line_count: u32,
+    stride: *const Stride,
+}
+
+// The worker only touches its own part of the buffers
+unsafe impl Send for Stripe {}
+
+impl Stripe {
+    unsafe fn run(self, worker: unsafe extern "C" fn(HTRANSFORM, *const c_void, *mut c_void, u32, u32, *const Stride)) {
+        worker(self.cargo, self.input, self.output, self.pixels_per_line, self.line_count, self.stride);
+    }
+}
+
+/// Distance between adjacent pixels of a single line
+fn pixel_step(format: PixelFormat) -> usize {
+    if format.planar() { format.bytes_per_channel() } else { format.bytes_per_pixel() }
+}
+
+unsafe extern "C" fn scheduler<E: Executor>(cargo: HTRANSFORM, input: *const c_void, output: *mut c_void, pixels_per_line: u32, line_count: u32, stride: *const Stride) {
+    let worker = match _cmsGetTransformWorker(cargo) {
+        Some(w) => w,
+        None => return,
+    };
+    let whole = Stripe { cargo, input, output, pixels_per_line, line_count, stride };
+
+    let max_workers = match _cmsGetTransformMaxWorkers(cargo) {
+        n if n > 0 => n as usize,
+        _ => abort_on_panic(E::max_workers),
+    };
+    let pixels = pixels_per_line as usize * line_count as usize;
+    let jobs = max_workers.min(pixels / MIN_PIXELS_PER_JOB);
+    if jobs < 2 {
+        return whole.run(worker);
+    }
+
+    let stripes: Vec<Stripe> = if line_count > 1 {
+        let s = &*stride;
+        split(line_count, jobs, |start, lines| Stripe {
+            input: input.cast::<u8>().add(start * s.BytesPerLineIn as usize).cast(),
+            output: output.cast::<u8>().add(start * s.BytesPerLineOut as usize).cast(),
+            line_count: lines,
+            ..whole
+        })
+    } else {
+        let in_step = pixel_step(cmsGetTransformInputFormat(cargo));
+        let out_step = pixel_step(cmsGetTransformOutputFormat(cargo));
+        split(pixels_per_line, jobs, |start, pixels| Stripe {
+            input: input.cast::<u8>().add(start * in_step).cast(),
+            output: output.cast::<u8>().add(start * out_step).cast(),
+            pixels_per_line: pixels,
+            ..whole
+        })
+    };
+
+    let jobs = stripes.into_iter().map(|stripe| -> Job<'_> {
+        Box::new(move || unsafe { stripe.run(worker) })
+    }).collect();
+    abort_on_panic(|| E::run(jobs));
+}
+
+/// Cuts `total` into `parts` contiguous, non-empty ranges
+fn split(total: u32, parts: usize, mut make: impl FnMut(usize, u32) -> Stripe) -> Vec<Stripe> {
+    let total = total as usize;
+    let parts = parts.min(total);
+    (0..parts).map(|i| {
+        let start = total * i / parts;
+        let end = total * (i + 1) / parts;
+        make(start, (end - start) as u32)
+    }).collect()
+}
+
+#[test]
+fn parallel_matches_serial() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static JOBS: AtomicUsize = AtomicUsize::new(0);
+    struct Threads;
+    unsafe impl Executor for Threads {
+        fn max_workers() -> usize { 4 }
+        fn run(jobs: Vec<Job<'_>>) {
+            JOBS.fetch_add(jobs.len(), Ordering::SeqCst);
+            std::thread::scope(|s| for job in jobs { s.spawn(job); });
+        }
+    }
+
+    unsafe fn convert(context: Context, input: &[u8], lines: u32) -> Vec<u16> {
+        let rgb = cmsCreate_sRGBProfileTHR(context);
+        let lab = cmsCreateLab4ProfileTHR(context, std::ptr::null());
+        let xform = cmsCreateTransformTHR(context, rgb, PixelFormat::RGB_8, lab, PixelFormat::Lab_16, Intent::Perceptual, 0);
+        assert!(!xform.is_null());
+        let mut out = vec![0u16; input.len()];
+        let width = (input.len() / 3) as u32 / lines;
+        cmsDoTransformLineStride(xform, input.as_ptr().cast(), out.as_mut_ptr().cast(), width, lines, width * 3, width * 6, 0, 0);
+        cmsDeleteTransform(xform);
+        cmsCloseProfile(rgb);
+        cmsCloseProfile(lab);
+        out
+    }
+
+    let input: Vec<u8> = (0..3 * 64 * 100).map(|i| (i * 7 % 251) as u8).collect();
+    unsafe {
+        let serial = cmsCreateContext(std::ptr::null_mut(), std::ptr::null_mut());
+        let parallel = cmsCreateContext(Parallelization::<Threads>::new().as_mut_ptr(), std::ptr::null_mut());
+
+        assert_eq!(convert(serial, &input, 100), convert(parallel, &input, 100));
+        assert_eq!(4, JOBS.load(Ordering::SeqCst));
+        assert_eq!(convert(serial, &input, 1), convert(parallel, &input, 1));
+        assert_eq!(8, JOBS.load(Ordering::SeqCst));
+
+        cmsDeleteContext(serial);
+        cmsDeleteContext(parallel);
+    }
+}
@@ -0,0 +1,178 @@
+//! Private tags and tag types, stored as Rust values.
+//!
+//! Register [`CustomTag`] in a context, and profiles opened or created in that context can
+//! [`read_tag`] and [`write_tag`] it. LCMS serializes it when the profile is saved.
+
+use super::{abort_on_panic, base, Plugin};
+use crate::ffi::*;
+use std::marker::PhantomData;
+use std::os::raw::c_void;
+use std::ptr;
+
+/// Contents of a tag, stored after the 8-byte type signature header
+pub trait TagType: Clone + 'static {
+    /// Type signature, e.g. `u32::from_be_bytes(*b"xcal")`. Must not clash with types LCMS already supports.
+    const TYPE_SIGNATURE: Signature;
+
+    /// Parses the tag data (without the type header). `None` makes reading of the tag fail.
+    fn read(data: &[u8]) -> Option<Self>;
+
+    /// Serializes the tag data (without the type header)
+    fn write(&self, out: &mut Vec<u8>);
+}
+
+// The public bindings take `TagSignature`, which can't hold signatures of private tags
+#[allow(clashing_extern_declarations)]
+extern "C" {
+    #[link_name = "cmsReadTag"]
+    fn cmsReadTagRaw(hProfile: HPROFILE, sig: Signature) -> *mut c_void;
+    #[link_name = "cmsWriteTag"]
+    fn cmsWriteTagRaw(hProfile: HPROFILE, sig: Signature, data: *const c_void) -> Bool;
+}
+
+/// `cmsPluginTagType` and `cmsPluginTag` pair that maps tag `tag` to the type `T`
+#[repr(C)]
+pub struct CustomTag<T> {
+    tag_type: PluginTagType,
+    tag: PluginTag,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T: TagType> CustomTag<T> {
+    /// `tag` is the tag signature, e.g. `u32::from_be_bytes(*b"xcal")`
+    #[must_use]
+    pub fn new(tag: Signature) -> Self {
+        let mut supported_types = [0; MAX_TYPES_IN_LCMS_PLUGIN];
+        supported_types[0] = T::TYPE_SIGNATURE;
+        Self {
+            tag_type: PluginTagType {
+                base: base(PluginTagTypeSig, 2060),
+                Handler: TagTypeHandler {
+                    Signature: T::TYPE_SIGNATURE,
+                    ReadPtr: Some(read::<T>),
+                    WritePtr: Some(write::<T>),
+                    DupPtr: Some(dup::<T>),
+                    FreePtr: Some(free::<T>),
+                    ContextID: ptr::null_mut(),
+                    ICCVersion: 0,
+                },
+            },
+            tag: PluginTag {
+                base: base(PluginTagSig, 2060),
+                Signature: tag,
+                Descriptor: TagDescriptor {
+                    ElemCount: 1,
+                    nSupportedTypes: 1,
+                    SupportedTypes: supported_types,
+                    DecideType: Some(decide_type::<T>),
+                },
+            },
+            _type: PhantomData,
+        }
+    }
+}
+
+unsafe impl<T> Plugin for CustomTag<T> {
+    fn as_mut_ptr(&mut self) -> *mut c_void {
+        // Linked here, because the struct may have moved since `new`
+        self.tag_type.base.Next = &mut self.tag.base;
+        (&mut self.tag_type as *mut PluginTagType).cast()
+    }
+}
+
+/// Reads and clones the tag. `None` if the tag is missing or couldn't be parsed.
+///
+/// # Safety
+///
+/// `profile` must be valid, and its context must have `CustomTag<T>` registered for `tag`.
+pub unsafe fn read_tag<T: TagType>(profile: HPROFILE, tag: Signature) -> Option<T> {
+    cmsReadTagRaw(profile, tag).cast::<T>().as_ref().cloned()
+}
+
+/// Adds or replaces the tag. The profile keeps its own copy.
+///
+/// # Safety
+///
+/// `profile` must be valid, and its context must have `CustomTag<T>` registered for `tag`.
+pub unsafe fn write_tag<T: TagType>(profile: HPROFILE, tag: Signature, value: &T) -> bool {
+    cmsWriteTagRaw(profile, tag, (value as *const T).cast()) != 0
+}
+
+unsafe extern "C" fn read<T: TagType>(_: *mut TagTypeHandler, io: *mut IOHANDLER, n_items: *mut u32, size: u32) -> *mut c_void {
+    // The size comes from the file, so don't trust it for the allocation
+    let mut data = Vec::with_capacity((size as usize).min(1 << 16));
+    for _ in 0..size {
+        let mut byte = 0;
+        if _cmsReadUInt8Number(io, &mut byte) == 0 {
+            return ptr::null_mut();
+        }
+        data.push(byte);
+    }
+    match abort_on_panic(|| T::read(&data)) {
+        Some(value) => {
+            *n_items = 1;
+            Box::into_raw(Box::new(value)).cast()
+        },
+        None => ptr::null_mut(),
+    }
+}
+
+unsafe extern "C" fn write<T: TagType>(_: *mut TagTypeHandler, io: *mut IOHANDLER, value: *mut c_void, _: u32) -> Bool {
+    let mut data = Vec::new();
+    abort_on_panic(|| (*value.cast::<T>()).write(&mut data));
+    data.into_iter().all(|byte| _cmsWriteUInt8Number(io, byte) != 0).into()
+}
+
+unsafe extern "C" fn dup<T: TagType>(_: *mut TagTypeHandler, value: *const c_void, _: u32) -> *mut c_void {
+    abort_on_panic(|| Box::into_raw(Box::new((*value.cast::<T>()).clone())).cast())
+}
+
+unsafe extern "C" fn free<T: TagType>(_: *mut TagTypeHandler, value: *mut c_void) {
+    if !value.is_null() {
+        abort_on_panic(|| drop(Box::from_raw(value.cast::<T>())));
+    }
+}
+
+unsafe extern "C" fn decide_type<T: TagType>(_: f64, _: *const c_void) -> Signature {
+    T::TYPE_SIGNATURE
+}
+
+#[test]
+fn roundtrip_private_tag() {
+    #[derive(Clone, Debug, PartialEq)]
+    struct Calibration {
+        exposure: u32,
+        name: String,
+    }
+    impl TagType for Calibration {
+        const TYPE_SIGNATURE: Signature = u32::from_be_bytes(*b"xcal");
+        fn read(data: &[u8]) -> Option<Self> {
+            let exposure = u32::from_be_bytes(data.get(..4)?.try_into().ok()?);
+            let name = String::from_utf8(data[4..].to_vec()).ok()?;
+            Some(Self { exposure, name })
+        }
+        fn write(&self, out: &mut Vec<u8>) {
+            out.extend_from_slice(&self.exposure.to_be_bytes());
+            out.extend_from_slice(self.name.as_bytes());
+        }
+    }
+
+    let tag = u32::from_be_bytes(*b"xCAL");
+    let value = Calibration { exposure: 1234, name: "bench 3".into() };
+    unsafe {
+        let context = cmsCreateContext(CustomTag::<Calibration>::new(tag).as_mut_ptr(), ptr::null_mut());
+        let profile = cmsCreate_sRGBProfileTHR(context);
+        assert!(write_tag(profile, tag, &value));
+
+        let mut len = 0;
+        assert_ne!(0, cmsSaveProfileToMem(profile, ptr::null_mut(), &mut len));
+        let mut icc = vec![0u8; len as usize];
+        assert_ne!(0, cmsSaveProfileToMem(profile, icc.as_mut_ptr().cast(), &mut len));
+        cmsCloseProfile(profile);
+
+        let profile = cmsOpenProfileFromMemTHR(context, icc.as_ptr().cast(), len);
+        assert_eq!(Some(value), read_tag::<Calibration>(profile, tag));
+        cmsCloseProfile(profile);
+        cmsDeleteContext(context);
+    }
+}
@@ -0,0 +1,214 @@
+//! Whole transforms implemented in Rust.
+//!
+//! When a transform is created, LCMS offers its unoptimized pipeline and pixel formats to each registered
+//! [`Kernel`]. A kernel can take over the transform, e.g. with a hand-vectorized loop for a specific pair of formats,
+//! or decline it, and LCMS will handle the transform as usual.
+//!
+//! Transforms taken over by a kernel can't change formats with `cmsChangeBuffersFormat`,
+//! and aren't offered to kernels when `FLAGS_NOOPTIMIZE` is set.
+
+use super::{abort_on_panic, base, Plugin};
+use crate::ffi::*;
+use std::marker::PhantomData;
+use std::os::raw::c_void;
+use std::slice;
+
+/// Transforms lines of pixels. It's shared by all threads using the transform.
+pub trait Kernel: Send + Sync + Sized + 'static {
+    /// Returns `None` to decline, and let other plug-ins or LCMS handle the transform.
+    ///
+    /// Transforms with planar formats are never offered.
+    fn create(request: &Request<'_>) -> Option<Self>;
+
+    /// Transforms `pixels` pixels of one line. The slices have exactly `pixels` × `bytes_per_pixel()` bytes of their format.
+    ///
+    /// Extra channels (e.g. alpha) are the kernel's responsibility, even if `FLAGS_COPY_ALPHA` is set.
+    fn transform_line(&self, input: &[u8], output: &mut [u8], pixels: usize);
+}
+
+/// Transform being created
+pub struct Request<'a> {
+    lut: *mut Pipeline,
+    input_format: u32,
+    output_format: u32,
+    flags: &'a mut u32,
+}
+
+impl Request<'_> {
+    /// The whole pipeline, not optimized yet. It stays owned by the transform.
+    #[must_use]
+    pub fn pipeline(&self) -> *mut Pipeline {
+        self.lut
+    }
+
+    #[must_use]
+    pub fn input_format(&self) -> PixelFormat {
+        PixelFormat(self.input_format)
+    }
+
+    #[must_use]
+    pub fn output_format(&self) -> PixelFormat {
+        PixelFormat(self.output_format)
+    }
+
+    /// `FLAGS_*` of the transform
+    #[must_use]
+    pub fn flags(&self) -> u32 {
+        *self.flags
+    }
+
+    /// Evaluates the pipeline with LCMS (`cmsPipelineEval16`), e.g. to precompute tables for the kernel.
+    ///
+    /// Slices must have at least as many elements as the pipeline has input and output channels.
+    pub fn eval16(&self, input: &[u16], output: &mut [u16]) {
+        unsafe {
+            assert!(input.len() >= cmsPipelineInputChannels(self.lut) as usize);
+            assert!(output.len() >= cmsPipelineOutputChannels(self.lut) as usize);
+            cmsPipelineEval16(input.as_ptr(), output.as_mut_ptr(), self.lut);
+        }
+    }
+}
+
+/// `cmsPluginTransform` for `K`
+#[repr(transparent)]
+pub struct TransformPlugin<K> {
+    plugin: PluginTransform,
+    _kernel: PhantomData<fn() -> K>,
+}
+
+impl<K: Kernel> TransformPlugin<K> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            plugin: PluginTransform {
+                // Versions before 2080 expect the legacy transform function
+                base: base(PluginTransformSig, 2080),
+                factories: PluginTransformFactories { xform: Some(factory::<K>) },
+            },
+            _kernel: PhantomData,
+        }
+    }
+}
+
+impl<K: Kernel> Default for TransformPlugin<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<K> Plugin for TransformPlugin<K> {
+    fn as_mut_ptr(&mut self) -> *mut c_void {
+        (&mut self.plugin as *mut PluginTransform).cast()
+    }
+}
+
+struct Private<K> {
+    kernel: K,
+    input_bpp: usize,
+    output_bpp: usize,
+}
+
+unsafe extern "C" fn factory<K: Kernel>(xform: *mut Transform2Fn, user_data: *mut *mut c_void, free_user_data: *mut FreeUserDataFn,
+    lut: *mut *mut Pipeline, input_format: *mut u32, output_format: *mut u32, flags: *mut u32) -> Bool {
+    let input = PixelFormat(*input_format);
+    let output = PixelFormat(*output_format);
+    if input.planar() || output.planar() {
+        return 0;
+    }
+    let request = Request {
+        lut: *lut,
+        input_format: input.0,
+        output_format: output.0,
+        flags: &mut *flags,
+    };
+    match abort_on_panic(|| K::create(&request)) {
+        Some(kernel) => {
+            *user_data = Box::into_raw(Box::new(Private {
+                kernel,
+                input_bpp: input.bytes_per_pixel(),
+                output_bpp: output.bytes_per_pixel(),
+            })).cast();
+            *free_user_data = Some(free::<K>);
+            *xform = Some(transform::<K>);
+            1
+        },
+        None => 0,
+    }
+}
+
+unsafe extern "C" fn free<K: Kernel>(_: Context, data: *mut c_void) {
+    if !data.is_null() {
+        abort_on_panic(|| drop(Box::from_raw(data.cast::<Private<K>>())));
+    }
+}
+
+unsafe extern "C" fn transform<K: Kernel>(cargo: HTRANSFORM, input: *const c_void, output: *mut c_void,
+    pixels_per_line: u32, line_count: u32, stride: *const Stride) {
+    let data = &*_cmsGetTransformUserData(cargo).cast::<Private<K>>();
+    let stride = &*stride;
+    let pixels = pixels_per_line as usize;
+    let in_len = pixels * data.input_bpp;
+    let out_len = pixels * data.output_bpp;
+    let mut copy = Vec::new();
+    abort_on_panic(|| {
+        for line in 0..line_count as usize {
+            let in_line = input.cast::<u8>().add(line * stride.BytesPerLineIn as usize);
+            let out_line = output.cast::<u8>().add(line * stride.BytesPerLineOut as usize);
+            // In-place transforms can't have a shared and a mutable slice of the same memory
+            let overlaps = (in_line as usize) < out_line as usize + out_len && (out_line as usize) < in_line as usize + in_len;
+            let in_slice = if overlaps {
+                copy.clear();
+                copy.extend_from_slice(slice::from_raw_parts(in_line, in_len));
+                &copy[..]
+            } else {
+                slice::from_raw_parts(in_line, in_len)
+            };
+            data.kernel.transform_line(in_slice, slice::from_raw_parts_mut(out_line, out_len), pixels);
+        }
+    });
+}
+
+#[test]
+fn inverting_kernel() {
+    struct Invert;
+    impl Kernel for Invert {
+        fn create(request: &Request<'_>) -> Option<Self> {
+            if request.input_format() != PixelFormat::RGB_8 || request.output_format() != PixelFormat::RGB_8 {
+                return None;
+            }
+            let mut out = [0u16; 3];
+            request.eval16(&[0, 0x8000, 0xFFFF], &mut out);
+            assert!(out[0] < 0x100 && out[2] > 0xFF00, "{out:?}");
+            Some(Self)
+        }
+
+        fn transform_line(&self, input: &[u8], output: &mut [u8], pixels: usize) {
+            assert_eq!(pixels * 3, input.len());
+            for (o, &i) in output.iter_mut().zip(input) {
+                *o = !i;
+            }
+        }
+    }
+
+    unsafe {
+        let context = cmsCreateContext(TransformPlugin::<Invert>::new().as_mut_ptr(), std::ptr::null_mut());
+        let profile = cmsCreate_sRGBProfileTHR(context);
+        let xform = cmsCreateTransformTHR(context, profile, PixelFormat::RGB_8, profile, PixelFormat::RGB_8, Intent::Perceptual, 0);
+        assert!(!xform.is_null());
+        let mut pixels = [0u8, 100, 255, 1, 2, 3];
+        cmsDoTransform(xform, pixels.as_ptr().cast(), pixels.as_mut_ptr().cast(), 2);
+        assert_eq!([255, 155, 0, 254, 253, 252], pixels);
+        cmsDeleteTransform(xform);
+
+        // Other formats fall back to LCMS
+        let xform = cmsCreateTransformTHR(context, profile, PixelFormat::RGB_16, profile, PixelFormat::RGB_16, Intent::Perceptual, 0);
+        assert!(!xform.is_null());
+        let mut out = [0u16; 3];
+        cmsDoTransform(xform, [0u16, 1000, 65535].as_ptr().cast(), out.as_mut_ptr().cast(), 1);
+        assert!(out[0] < 10 && out[2] > 65525, "{out:?}");
+
+        cmsDeleteTransform(xform);
+        cmsCloseProfile(profile);
+        cmsDeleteContext(context);
+    }
+}
@@ -0,0 +1,617 @@
+//! Owned tone curves that free themselves.
+
+use crate::ffi::*;
+use std::error::Error;
+use std::fmt;
+use std::ptr::NonNull;
+use std::slice;
+
+/// Owned `*mut ToneCurve`, freed with `cmsFreeToneCurve`
+pub struct Curve(NonNull<ToneCurve>);
+
+// LCMS doesn't mutate curves when evaluating them
+unsafe impl Send for Curve {}
+unsafe impl Sync for Curve {}
+
+impl Curve {
+    /// Takes ownership of the curve. `None` if it's null.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be a valid curve that nothing else will free.
+    #[must_use]
+    pub unsafe fn from_ptr(curve: *mut ToneCurve) -> Option<Self> {
+        NonNull::new(curve).map(Self)
+    }
+
+    /// The curve stays owned by `self`
+    #[must_use]
+    pub fn as_ptr(&self) -> *mut ToneCurve {
+        self.0.as_ptr()
+    }
+
+    /// Gives up ownership. The caller has to free the curve.
+    #[must_use]
+    pub fn into_ptr(self) -> *mut ToneCurve {
+        let ptr = self.0.as_ptr();
+        std::mem::forget(self);
+        ptr
+    }
+
+    /// Simple `y = x^gamma` curve (`cmsBuildGamma`)
+    #[must_use]
+    pub fn gamma(gamma: f64) -> Option<Self> {
+        unsafe { Self::gamma_thr(std::ptr::null_mut(), gamma) }
+    }
+
+    /// Same as [`Curve::gamma`], allocated in the `context`
+    ///
+    /// # Safety
+    ///
+    /// `context` must be valid or null.
+    #[must_use]
+    pub unsafe fn gamma_thr(context: Context, gamma: f64) -> Option<Self> {
+        Self::from_ptr(cmsBuildGamma(context, gamma))
+    }
+
+    /// Curve of a built-in or plug-in parametric `curve_type` (`cmsBuildParametricToneCurve`).
+    ///
+    /// Missing parameters are 0. At most 10 are used.
+    #[must_use]
+    pub fn parametric(curve_type: i32, params: &[f64]) -> Option<Self> {
+        unsafe { Self::parametric_thr(std::ptr::null_mut(), curve_type, params) }
+    }
+
+    /// Same as [`Curve::parametric`], allocated in the `context`. Parametric types from plug-ins need their context.
+    ///
+    /// # Safety
+    ///
+    /// `context` must be valid or null.
+    #[must_use]
+    pub unsafe fn parametric_thr(context: Context, curve_type: i32, params: &[f64]) -> Option<Self> {
+        let mut all = [0.; 10];
+        for (a, &p) in all.iter_mut().zip(params) {
+            *a = p;
+        }
+        Self::from_ptr(cmsBuildParametricToneCurve(context, curve_type, all.as_ptr()))
+    }
+
+    /// `cmsBuildSegmentedToneCurve`
+    ///
+    /// # Safety
+    ///
+    /// `context` must be valid or null. `SampledPoints` of sampled segments must point to `nGridPoints` values.
+    #[must_use]
+    pub unsafe fn segmented(context: Context, segments: &[CurveSegment]) -> Option<Self> {
+        Self::from_ptr(cmsBuildSegmentedToneCurve(context, segments.len() as u32, segments.as_ptr()))
+    }
+
+    /// Curve interpolating evenly spaced `values` (`cmsBuildTabulatedToneCurve16`)
+    #[must_use]
+    pub fn tabulated_u16(values: &[u16]) -> Option<Self> {
+        unsafe { Self::tabulated_u16_thr(std::ptr::null_mut(), values) }
+    }
+
+    /// Same as [`Curve::tabulated_u16`], allocated in the `context`
+    ///
+    /// # Safety
+    ///
+    /// `context` must be valid or null.
+    #[must_use]
+    pub unsafe fn tabulated_u16_thr(context: Context, values: &[u16]) -> Option<Self> {
+        Self::from_ptr(cmsBuildTabulatedToneCurve16(context, values.len() as u32, values.as_ptr()))
+    }
+
+    /// Curve interpolating evenly spaced `values` in 0..=1 range (`cmsBuildTabulatedToneCurveFloat`)
+    #[must_use]
+    pub fn tabulated_f32(values: &[f32]) -> Option<Self> {
+        unsafe { Self::tabulated_f32_thr(std::ptr::null_mut(), values) }
+    }
+
+    /// Same as [`Curve::tabulated_f32`], allocated in the `context`
+    ///
+    /// # Safety
+    ///
+    /// `context` must be valid or null.
+    #[must_use]
+    pub unsafe fn tabulated_f32_thr(context: Context, values: &[f32]) -> Option<Self> {
+        Self::from_ptr(cmsBuildTabulatedToneCurveFloat(context, values.len() as u32, values.as_ptr()))
+    }
+
+    /// Evaluates in full precision. Input and output are in 0..=1 range, although segmented curves may go beyond it.
+    #[must_use]
+    pub fn eval_f32(&self, v: f32) -> f32 {
+        unsafe { cmsEvalToneCurveFloat(self.as_ptr(), v) }
+    }
+
+    /// Evaluates using the 16-bit table
+    #[must_use]
+    pub fn eval_u16(&self, v: u16) -> u16 {
+        unsafe { cmsEvalToneCurve16(self.as_ptr(), v) }
+    }
+
+    /// Inverse of the curve (`cmsReverseToneCurve`)
+    #[must_use]
+    pub fn reverse(&self) -> Option<Self> {
+        unsafe { Self::from_ptr(cmsReverseToneCurve(self.as_ptr())) }
+    }
+
+    /// Inverse of the curve, tabulated with `samples` entries if it's not parametric (`cmsReverseToneCurveEx`)
+    #[must_use]
+    pub fn reverse_with_samples(&self, samples: u32) -> Option<Self> {
+        unsafe { Self::from_ptr(cmsReverseToneCurveEx(samples, self.as_ptr())) }
+    }
+
+    /// Curve that maps through `self`, and then through the inverse of `other` (`cmsJoinToneCurve`)
+    #[must_use]
+    pub fn join(&self, other: &Self, points: u32) -> Option<Self> {
+        unsafe { Self::from_ptr(cmsJoinToneCurve(std::ptr::null_mut(), self.as_ptr(), other.as_ptr(), points)) }
+    }
+
+    /// Smooths the table of a tabulated curve (`cmsSmoothToneCurve`). Returns `false` on failure.
+    pub fn smooth(&mut self, lambda: f64) -> bool {
+        unsafe { cmsSmoothToneCurve(self.as_ptr(), lambda) != 0 }
+    }
+
+    /// Approximate gamma (`cmsEstimateGamma`). `None` if the curve isn't a gamma-like curve.
+    #[must_use]
+    pub fn estimate_gamma(&self, precision: f64) -> Option<f64> {
+        let gamma = unsafe { cmsEstimateGamma(self.as_ptr(), precision) };
+        if gamma > 0. { Some(gamma) } else { None }
+    }
+
+    #[must_use]
+    pub fn is_linear(&self) -> bool {
+        unsafe { cmsIsToneCurveLinear(self.as_ptr()) != 0 }
+    }
+
+    #[must_use]
+    pub fn is_monotonic(&self) -> bool {
+        unsafe { cmsIsToneCurveMonotonic(self.as_ptr()) != 0 }
+    }
+
+    #[must_use]
+    pub fn is_descending(&self) -> bool {
+        unsafe { cmsIsToneCurveDescending(self.as_ptr()) != 0 }
+    }
+
+    #[must_use]
+    pub fn is_multisegment(&self) -> bool {
+        unsafe { cmsIsToneCurveMultisegment(self.as_ptr()) != 0 }
+    }
+
+    /// Type of the parametric curve, or 0 if it's not a single-segment parametric curve
+    #[must_use]
+    pub fn parametric_type(&self) -> i32 {
+        unsafe { cmsGetToneCurveParametricType(self.as_ptr()) }
+    }
+
+    /// Formula and parameters of a single-segment parametric curve, if it's one of the built-in types
+    #[must_use]
+    pub fn formula(&self) -> Option<Parametric> {
+        let params = self.params()?;
+        Parametric::new(self.parametric_type(), params)
+    }
+
+    /// All 10 parameters of a single-segment parametric curve, including ones of plug-in types.
+    ///
+    /// The vendored LCMS has no `cmsGetToneCurveParams`, so they're read from the curve's only segment.
+    #[must_use]
+    pub fn params(&self) -> Option<&[f64; 10]> {
+        if self.parametric_type() == 0 || self.segment_count() != 1 {
+            return None;
+        }
+        Some(self.segments().next()?.params())
+    }
+
+    /// Number of segments. 0 for curves made only of a 16-bit table.
+    ///
+    /// LCMS has no getter for it, so segments are looked up until `cmsGetToneCurveSegment` returns null.
+    #[must_use]
+    pub fn segment_count(&self) -> usize {
+        (0..i32::MAX).take_while(|&n| unsafe { !cmsGetToneCurveSegment(n, self.as_ptr()).is_null() }).count()
+    }
+
+    /// Segments in order of their domain. Counted up front, with [`Curve::segment_count`].
+    #[must_use]
+    pub fn segments(&self) -> Segments<'_> {
+        Segments { curve: self, next: 0, len: self.segment_count() }
+    }
+
+    /// 16-bit table LCMS uses for `eval_u16` and in 16-bit transforms
+    #[must_use]
+    pub fn estimated_table(&self) -> &[u16] {
+        unsafe {
+            let len = cmsGetToneCurveEstimatedTableEntries(self.as_ptr()) as usize;
+            let table = cmsGetToneCurveEstimatedTable(self.as_ptr());
+            if table.is_null() {
+                return &[];
+            }
+            slice::from_raw_parts(table, len)
+        }
+    }
+}
+
+/// Iterator returned by [`Curve::segments`]
+pub struct Segments<'a> {
+    curve: &'a Curve,
+    next: usize,
+    len: usize,
+}
+
+impl<'a> Iterator for Segments<'a> {
+    type Item = SegmentRef<'a>;
+
+    fn next(&mut self) -> Option<SegmentRef<'a>> {
+        if self.next >= self.len {
+            return None;
+        }
+        let segment = unsafe { cmsGetToneCurveSegment(self.next as i32, self.curve.as_ptr()).as_ref()? };
+        self.next += 1;
+        Some(SegmentRef(segment))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.len - self.next;
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for Segments<'_> {}
+
+/// One segment of a curve, covering `x0 < X ≤ x1`
+#[derive(Copy, Clone)]
+pub struct SegmentRef<'a>(&'a CurveSegment);
+
+impl<'a> SegmentRef<'a> {
+    #[must_use]
+    pub fn x0(&self) -> f32 {
+        self.0.x0
+    }
+
+    #[must_use]
+    pub fn x1(&self) -> f32 {
+        self.0.x1
+    }
+
+    /// Parametric type (possibly from a plug-in), or 0 if the segment is sampled
+    #[must_use]
+    pub fn curve_type(&self) -> i32 {
+        self.0.Type
+    }
+
+    /// Parameters of the formula. All zeros for sampled segments.
+    #[must_use]
+    pub fn params(&self) -> &'a [f64; 10] {
+        &self.0.Params
+    }
+
+    /// Decoded formula, if it's one of the built-in types
+    #[must_use]
+    pub fn formula(&self) -> Option<Parametric> {
+        Parametric::new(self.0.Type, &self.0.Params)
+    }
+
+    /// Points evenly spaced from `x0` to `x1`. `None` if the segment is a formula.
+    #[must_use]
+    pub fn samples(&self) -> Option<&'a [f32]> {
+        if self.0.Type != 0 || self.0.SampledPoints.is_null() {
+            return None;
+        }
+        Some(unsafe { slice::from_raw_parts(self.0.SampledPoints, self.0.nGridPoints as usize) })
+    }
+
+    /// The raw segment
+    #[must_use]
+    pub fn as_segment(&self) -> &'a CurveSegment {
+        self.0
+    }
+}
+
+impl fmt::Debug for SegmentRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("Segment");
+        d.field("x0", &self.x0()).field("x1", &self.x1());
+        match (self.samples(), self.formula()) {
+            (Some(samples), _) => d.field("samples", &samples),
+            (None, Some(formula)) => d.field("formula", &formula),
+            (None, None) => d.field("curve_type", &self.curve_type()).field("params", self.params()),
+        };
+        d.finish()
+    }
+}
+
+/// Built-in parametric curve, with the formula analytically inverted if the curve type is negative
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Parametric {
+    pub formula: Formula,
+    pub inverted: bool,
+}
+
+/// Parametric curve families of ICC (types 1-5) and LCMS (types 6-8, 108 and 109)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Formula {
+    /// Type 1: Y = X^gamma
+    Gamma { gamma: f64 },
+    /// Type 2, CIE 122-1966: Y = (aX + b)^gamma for X ≥ -b/a, otherwise 0
+    Cie122 { gamma: f64, a: f64, b: f64 },
+    /// Type 3, IEC 61966-3: Y = (aX + b)^gamma + c for X ≥ -b/a, otherwise c
+    Iec61966_3 { gamma: f64, a: f64, b: f64, c: f64 },
+    /// Type 4, IEC 61966-2.1 (sRGB-like): Y = (aX + b)^gamma for X ≥ d, otherwise cX
+    Srgb { gamma: f64, a: f64, b: f64, c: f64, d: f64 },
+    /// Type 5: Y = (aX + b)^gamma + e for X ≥ d, otherwise cX + f
+    SrgbOffset { gamma: f64, a: f64, b: f64, c: f64, d: f64, e: f64, f: f64 },
+    /// Type 6: Y = (aX + b)^gamma + c
+    PowerOffset { gamma: f64, a: f64, b: f64, c: f64 },
+    /// Type 7: Y = a·log₁₀(bX^gamma + c) + d
+    Log { gamma: f64, a: f64, b: f64, c: f64, d: f64 },
+    /// Type 8: Y = a·b^(cX + d) + e
+    Exp { a: f64, b: f64, c: f64, d: f64, e: f64 },
+    /// Type 108, S-shaped: Y = (1 - (1 - X)^(1/gamma))^(1/gamma)
+    SShaped { gamma: f64 },
+    /// Type 109, sigmoidal with steepness `k`
+    Sigmoid { k: f64 },
+}
+
+impl Parametric {
+    /// `None` if `curve_type` isn't a built-in type
+    #[must_use]
+    pub fn new(curve_type: i32, params: &[f64; 10]) -> Option<Self> {
+        let [gamma, a, b, c, d, e, f, ..] = *params;
+        let formula = match curve_type.abs() {
+            1 => Formula::Gamma { gamma },
+            2 => Formula::Cie122 { gamma, a, b },
+            3 => Formula::Iec61966_3 { gamma, a, b, c },
+            4 => Formula::Srgb { gamma, a, b, c, d },
+            5 => Formula::SrgbOffset { gamma, a, b, c, d, e, f },
+            6 => Formula::PowerOffset { gamma, a, b, c },
+            7 => Formula::Log { gamma, a, b, c, d },
+            // No gamma, so parameters start at a
+            8 => Formula::Exp { a: params[0], b: params[1], c: params[2], d: params[3], e: params[4] },
+            108 => Formula::SShaped { gamma },
+            109 => Formula::Sigmoid { k: params[0] },
+            _ => return None,
+        };
+        Some(Self { formula, inverted: curve_type < 0 })
+    }
+
+    /// Type for `cmsBuildParametricToneCurve`
+    #[must_use]
+    pub fn curve_type(&self) -> i32 {
+        let t = match self.formula {
+            Formula::Gamma { .. } => 1,
+            Formula::Cie122 { .. } => 2,
+            Formula::Iec61966_3 { .. } => 3,
+            Formula::Srgb { .. } => 4,
+            Formula::SrgbOffset { .. } => 5,
+            Formula::PowerOffset { .. } => 6,
+            Formula::Log { .. } => 7,
+            Formula::Exp { .. } => 8,
+            Formula::SShaped { .. } => 108,
+            Formula::Sigmoid { .. } => 109,
+        };
+        if self.inverted { -t } else { t }
+    }
+
+    /// Parameters for `cmsBuildParametricToneCurve`
+    #[must_use]
+    pub fn params(&self) -> [f64; 10] {
+        let p: &[f64] = match self.formula {
+            Formula::Gamma { gamma } | Formula::SShaped { gamma } => &[gamma],
+            Formula::Cie122 { gamma, a, b } => &[gamma, a, b],
+            Formula::Iec61966_3 { gamma, a, b, c } | Formula::PowerOffset { gamma, a, b, c } => &[gamma, a, b, c],
+            Formula::Srgb { gamma, a, b, c, d } | Formula::Log { gamma, a, b, c, d } => &[gamma, a, b, c, d],
+            Formula::SrgbOffset { gamma, a, b, c, d, e, f } => &[gamma, a, b, c, d, e, f],
+            Formula::Exp { a, b, c, d, e } => &[a, b, c, d, e],
+            Formula::Sigmoid { k } => &[k],
+        };
+        let mut params = [0.; 10];
+        params[..p.len()].copy_from_slice(p);
+        params
+    }
+
+    #[must_use]
+    pub fn to_curve(&self) -> Option<Curve> {
+        Curve::parametric(self.curve_type(), &self.params())
+    }
+}
+
+/// Builds a curve out of formula and sampled segments (`cmsBuildSegmentedToneCurve`).
+///
+/// Segments must be added in order, and each must start where the previous one ends.
+/// A segment covers `x0 < X ≤ x1`. Use infinity (or LCMS' customary ±1e22) for open ends.
+#[derive(Debug, Clone, Default)]
+pub struct CurveBuilder {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone)]
+struct Segment {
+    x0: f32,
+    x1: f32,
+    curve_type: i32,
+    params: [f64; 10],
+    samples: Vec<f32>,
+}
+
+/// Why [`CurveBuilder::build`] failed. Indices are of segments, in the order they were added.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SegmentError {
+    NoSegments,
+    /// `x1` isn't greater than `x0`
+    EmptyRange(usize),
+    /// Doesn't start where the previous segment ends
+    NotContiguous(usize),
+    /// Sampled segments (and formulas of type 0) need at least 2 points
+    TooFewSamples(usize),
+    /// LCMS couldn't build the curve
+    Failed,
+}
+
+impl fmt::Display for SegmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::NoSegments => f.write_str("curve has no segments"),
+            Self::EmptyRange(i) => write!(f, "segment {i} has an empty range"),
+            Self::NotContiguous(i) => write!(f, "segment {i} doesn't start where the previous one ends"),
+            Self::TooFewSamples(i) => write!(f, "segment {i} has fewer than 2 samples"),
+            Self::Failed => f.write_str("LCMS couldn't build the curve"),
+        }
+    }
+}
+
+impl Error for SegmentError {}
+
+impl CurveBuilder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Segment evaluated with a parametric formula of `curve_type` (see `cmsBuildParametricToneCurve`). Missing parameters are 0.
+    #[must_use]
+    pub fn formula(mut self, x0: f32, x1: f32, curve_type: i32, params: &[f64]) -> Self {
+        let mut all = [0.; 10];
+        for (a, &p) in all.iter_mut().zip(params) {
+            *a = p;
+        }
+        self.segments.push(Segment { x0, x1, curve_type, params: all, samples: Vec::new() });
+        self
+    }
+
+    /// Segment evaluated with a built-in formula
+    #[must_use]
+    pub fn parametric(self, x0: f32, x1: f32, parametric: &Parametric) -> Self {
+        self.formula(x0, x1, parametric.curve_type(), &parametric.params())
+    }
+
+    /// Segment interpolating `samples`, which are evenly spaced from `x0` to `x1` inclusive
+    #[must_use]
+    pub fn sampled(mut self, x0: f32, x1: f32, samples: Vec<f32>) -> Self {
+        self.segments.push(Segment { x0, x1, curve_type: 0, params: [0.; 10], samples });
+        self
+    }
+
+    /// Checks the segments and builds the curve
+    pub fn build(&self) -> Result<Curve, SegmentError> {
+        unsafe { self.build_thr(std::ptr::null_mut()) }
+    }
+
+    /// Same as [`CurveBuilder::build`], allocated in the `context`. Formulas from plug-ins need their context.
+    ///
+    /// # Safety
+    ///
+    /// `context` must be valid or null.
+    pub unsafe fn build_thr(&self, context: Context) -> Result<Curve, SegmentError> {
+        if self.segments.is_empty() {
+            return Err(SegmentError::NoSegments);
+        }
+        for (i, s) in self.segments.iter().enumerate() {
+            if s.x0 >= s.x1 || s.x0.is_nan() || s.x1.is_nan() {
+                return Err(SegmentError::EmptyRange(i));
+            }
+            if i > 0 && s.x0 != self.segments[i - 1].x1 {
+                return Err(SegmentError::NotContiguous(i));
+            }
+            if s.curve_type == 0 && s.samples.len() < 2 {
+                return Err(SegmentError::TooFewSamples(i));
+            }
+        }
+        // LCMS copies the samples, so the pointers only need to live until the curve is built
+        let segments: Vec<_> = self.segments.iter().map(|s| CurveSegment {
+            x0: s.x0,
+            x1: s.x1,
+            Type: s.curve_type,
+            Params: s.params,
+            nGridPoints: s.samples.len() as u32,
+            SampledPoints: if s.samples.is_empty() { std::ptr::null_mut() } else { s.samples.as_ptr() as *mut f32 },
+        }).collect();
+        Curve::segmented(context, &segments).ok_or(SegmentError::Failed)
+    }
+}
+
+impl Clone for Curve {
+    /// # Panics
+    ///
+    /// If LCMS runs out of memory
+    fn clone(&self) -> Self {
+        unsafe { Self::from_ptr(cmsDupToneCurve(self.as_ptr())) }.expect("cmsDupToneCurve")
+    }
+}
+
+impl Drop for Curve {
+    fn drop(&mut self) {
+        unsafe { cmsFreeToneCurve(self.as_ptr()) }
+    }
+}
+
+impl fmt::Debug for Curve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Curve")
+            .field("parametric_type", &self.parametric_type())
+            .field("entries", &self.estimated_table().len())
+            .finish()
+    }
+}
+
+#[test]
+fn gamma_curve() {
+    let curve = Curve::gamma(2.2).unwrap();
+    assert_eq!(1, curve.parametric_type());
+    assert!((curve.eval_f32(0.5) - 0.5f32.powf(2.2)).abs() < 0.001);
+    assert!((curve.estimate_gamma(0.01).unwrap() - 2.2).abs() < 0.01);
+    assert!(curve.is_monotonic() && !curve.is_linear() && !curve.is_descending());
+    assert_eq!(0xFFFF, *curve.estimated_table().last().unwrap());
+
+    let inverse = curve.reverse().unwrap();
+    assert!((inverse.eval_f32(curve.eval_f32(0.3)) - 0.3).abs() < 0.001);
+    let identity = curve.join(&curve.clone(), 256).unwrap();
+    assert!(identity.is_linear());
+
+    let srgb = Parametric {
+        formula: Formula::Srgb { gamma: 2.4, a: 1. / 1.055, b: 0.055 / 1.055, c: 1. / 12.92, d: 0.04045 },
+        inverted: false,
+    };
+    let curve = srgb.to_curve().unwrap();
+    assert_eq!(Some(srgb), curve.formula());
+    assert_eq!(Some(Parametric { inverted: true, ..srgb }), curve.reverse().unwrap().formula());
+    assert!(curve.params().is_some());
+
+    let table = Curve::tabulated_u16(&[0, 0x1000, 0xFFFF]).unwrap();
+    assert!(table.eval_u16(0x8000).abs_diff(0x1000) <= 1);
+    let noisy: Vec<u16> = (0..256u32).map(|i| (i * 250 + (i % 3) * 40) as u16).collect();
+    let mut noisy = Curve::tabulated_u16(&noisy).unwrap();
+    assert!(noisy.smooth(1.));
+    assert!(Curve::tabulated_f32(&[]).is_none());
+}
+
+#[test]
+fn segmented_with_linear_toe() {
+    let toe = Parametric { formula: Formula::PowerOffset { gamma: 1., a: 4.5, b: 0., c: 0. }, inverted: false };
+    let curve = CurveBuilder::new()
+        .parametric(f32::NEG_INFINITY, 0.018, &toe)
+        .sampled(0.018, 1., vec![0.081, 0.5, 1.])
+        .formula(1., f32::INFINITY, 1, &[1.])
+        .build()
+        .unwrap();
+    assert!(curve.is_multisegment());
+    assert!((curve.eval_f32(0.01) - 0.045).abs() < 1e-5);
+    assert!((curve.eval_f32(0.509) - 0.5).abs() < 1e-5);
+    assert!((curve.eval_f32(2.) - 2.).abs() < 1e-5);
+
+    assert_eq!(3, curve.segment_count());
+    assert_eq!(3, curve.segments().len());
+    let segments: Vec<_> = curve.segments().collect();
+    assert_eq!(3, segments.len());
+    assert_eq!(Some(toe), segments[0].formula());
+    assert_eq!((0.018, 1.), (segments[1].x0(), segments[1].x1()));
+    assert_eq!(Some(&[0.081, 0.5, 1.][..]), segments[1].samples());
+    assert_eq!(None, segments[2].samples());
+    assert_eq!(0, Curve::tabulated_u16(&[0, 0xFFFF]).unwrap().segment_count());
+
+    assert_eq!(Some(SegmentError::NoSegments), CurveBuilder::new().build().err());
+    let gap = CurveBuilder::new().formula(f32::NEG_INFINITY, 0.5, 1, &[1.]).formula(0.6, 1., 1, &[1.]);
+    assert_eq!(Some(SegmentError::NotContiguous(1)), gap.build().err());
+    let short = CurveBuilder::new().sampled(0., 1., vec![1.]);
+    assert_eq!(Some(SegmentError::TooFewSamples(0)), short.build().err());
+}
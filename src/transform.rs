@@ -0,0 +1,214 @@
+//! Transforms typed by their pixels, so that buffers can't have a wrong format or length.
+
+use crate::ffi::*;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr::{self, NonNull};
+
+pub mod cache;
+pub mod chain;
+pub mod flags;
+pub mod info;
+pub mod named;
+pub mod proofing;
+
+pub use self::flags::{Flags, FlagsError};
+
+/// Pixel type with a fixed memory layout described by [`Pixel::FORMAT`]
+///
+/// # Safety
+///
+/// `FORMAT` must describe exactly `size_of::<Self>()` bytes per pixel, and every bit pattern LCMS can write must be valid for `Self`.
+/// All zeros must be valid too, e.g. for output buffers that are zeroed before LCMS writes to them (it may skip extra channels).
+pub unsafe trait Pixel: Copy + 'static {
+    const FORMAT: PixelFormat;
+}
+
+/// CMYK pixel, since `[T; 4]` is RGBA
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Cmyk<T>(pub [T; 4]);
+
+macro_rules! pixels {
+    ($($ty:ty => $format:ident),* $(,)?) => {
+        $(unsafe impl Pixel for $ty {
+            const FORMAT: PixelFormat = PixelFormat::$format;
+        })*
+    };
+}
+
+pixels! {
+    [u8; 1] => GRAY_8, [u8; 2] => GRAYA_8, [u8; 3] => RGB_8, [u8; 4] => RGBA_8, Cmyk<u8> => CMYK_8,
+    [u16; 1] => GRAY_16, [u16; 2] => GRAYA_16, [u16; 3] => RGB_16, [u16; 4] => RGBA_16, Cmyk<u16> => CMYK_16,
+    [f32; 1] => GRAY_FLT, [f32; 3] => RGB_FLT, [f32; 4] => RGBA_FLT, Cmyk<f32> => CMYK_FLT,
+    [f64; 1] => GRAY_DBL, [f64; 3] => RGB_DBL, Cmyk<f64> => CMYK_DBL,
+    CIELab => Lab_DBL, CIEXYZ => XYZ_DBL,
+}
+
+/// Owned `HTRANSFORM` from `In` pixels to `Out` pixels, deleted with `cmsDeleteTransform`
+pub struct Transform<In, Out> {
+    xform: NonNull<_HTRANSFORM>,
+    _pixels: PhantomData<fn(&In) -> Out>,
+}
+
+// LCMS transforms can be used from multiple threads at once
+unsafe impl<In, Out> Send for Transform<In, Out> {}
+unsafe impl<In, Out> Sync for Transform<In, Out> {}
+
+impl<In: Pixel, Out: Pixel> Transform<In, Out> {
+    /// `cmsCreateTransform` with the formats of `In` and `Out`.
+    ///
+    /// Fails if `flags` don't pass [`Flags::validate`], or LCMS can't link the profiles in these formats.
+    ///
+    /// # Safety
+    ///
+    /// Profiles must be valid. They can be closed once the transform is created.
+    pub unsafe fn new(input: HPROFILE, output: HPROFILE, intent: impl Into<IntentCode>, flags: Flags) -> Result<Self, TransformError> {
+        Self::new_thr(ptr::null_mut(), input, output, intent, flags)
+    }
+
+    /// Same as [`Transform::new`], allocated in the `context`
+    ///
+    /// # Safety
+    ///
+    /// Profiles must be valid. `context` must be valid or null.
+    pub unsafe fn new_thr(context: Context, input: HPROFILE, output: HPROFILE, intent: impl Into<IntentCode>, flags: Flags) -> Result<Self, TransformError> {
+        flags.validate().map_err(TransformError::Flags)?;
+        Self::from_ptr(crate::plugin::intents::create_transform(context, input, In::FORMAT, output, Out::FORMAT, intent, flags.bits()))
+            .ok_or(TransformError::Failed)
+    }
+
+    /// Takes ownership of the transform. `None` if it's null, or its formats aren't the formats of `In` and `Out`
+    /// (then the caller still owns it).
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be a valid transform that nothing else will delete.
+    #[must_use]
+    pub unsafe fn from_ptr(xform: HTRANSFORM) -> Option<Self> {
+        let xform = NonNull::new(xform)?;
+        if cmsGetTransformInputFormat(xform.as_ptr()) != In::FORMAT || cmsGetTransformOutputFormat(xform.as_ptr()) != Out::FORMAT {
+            return None;
+        }
+        Some(Self { xform, _pixels: PhantomData })
+    }
+
+    #[must_use]
+    pub fn as_ptr(&self) -> HTRANSFORM {
+        self.xform.as_ptr()
+    }
+
+    /// The caller becomes responsible for deleting the transform
+    #[must_use]
+    pub fn into_ptr(self) -> HTRANSFORM {
+        let ptr = self.as_ptr();
+        mem::forget(self);
+        ptr
+    }
+
+    #[must_use]
+    pub fn context(&self) -> Context {
+        unsafe { cmsGetTransformContextID(self.as_ptr()) }
+    }
+
+    /// Transforms all pixels of `input` into `output`.
+    ///
+    /// # Panics
+    ///
+    /// If the slices have different lengths.
+    pub fn transform(&self, input: &[In], output: &mut [Out]) {
+        assert_eq!(input.len(), output.len(), "input and output must have the same number of pixels");
+        debug_assert_eq!(In::FORMAT.bytes_per_pixel(), mem::size_of::<In>());
+        debug_assert_eq!(Out::FORMAT.bytes_per_pixel(), mem::size_of::<Out>());
+        for (input, output) in input.chunks(u32::MAX as usize).zip(output.chunks_mut(u32::MAX as usize)) {
+            unsafe {
+                cmsDoTransform(self.as_ptr(), input.as_ptr().cast(), output.as_mut_ptr().cast(), input.len() as u32);
+            }
+        }
+    }
+}
+
+impl<P: Pixel> Transform<P, P> {
+    /// Transforms pixels in the same buffer
+    pub fn transform_in_place(&self, pixels: &mut [P]) {
+        debug_assert_eq!(P::FORMAT.bytes_per_pixel(), mem::size_of::<P>());
+        for pixels in pixels.chunks_mut(u32::MAX as usize) {
+            unsafe {
+                cmsDoTransform(self.as_ptr(), pixels.as_ptr().cast(), pixels.as_mut_ptr().cast(), pixels.len() as u32);
+            }
+        }
+    }
+}
+
+/// Why a transform can't be created
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TransformError {
+    Flags(FlagsError),
+    /// Rejected by LCMS, e.g. for profiles that can't be linked or don't fit the pixel formats
+    Failed,
+}
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Flags(err) => err.fmt(f),
+            Self::Failed => f.write_str("LCMS failed to create the transform"),
+        }
+    }
+}
+
+impl Error for TransformError {}
+
+impl<In, Out> Drop for Transform<In, Out> {
+    fn drop(&mut self) {
+        unsafe { cmsDeleteTransform(self.xform.as_ptr()) }
+    }
+}
+
+impl<In, Out> fmt::Debug for Transform<In, Out> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        unsafe {
+            f.debug_struct("Transform")
+                .field("input_format", &cmsGetTransformInputFormat(self.xform.as_ptr()))
+                .field("output_format", &cmsGetTransformOutputFormat(self.xform.as_ptr()))
+                .finish()
+        }
+    }
+}
+
+#[test]
+fn srgb_to_lab() {
+    unsafe {
+        let srgb = cmsCreate_sRGBProfile();
+        let lab = cmsCreateLab4Profile(ptr::null());
+        let to_lab = Transform::<[u8; 3], CIELab>::new(srgb, lab, Intent::Perceptual, Flags::new()).unwrap();
+        let from_lab = Transform::<CIELab, [u16; 4]>::new(lab, srgb, Intent::Perceptual, Flags::new()).unwrap();
+        let rgba = Transform::<[u8; 4], [u8; 4]>::new(srgb, srgb, Intent::Perceptual, Flags::new().copy_alpha()).unwrap();
+        let err = Transform::<[u8; 3], [u8; 3]>::new(srgb, srgb, Intent::Perceptual, Flags::NO_OPTIMIZE.force_clut()).unwrap_err();
+        assert_eq!(TransformError::Flags(FlagsError::Conflict(Flags::NO_OPTIMIZE, Flags::FORCE_CLUT)), err);
+        assert_eq!(Err(TransformError::Failed), Transform::<[u8; 3], [u8; 1]>::new(srgb, srgb, Intent::Perceptual, Flags::new()).map(drop));
+        let raw = cmsCreateTransform(srgb, PixelFormat::RGB_8, srgb, PixelFormat::RGB_8, Intent::Perceptual, 0);
+        assert!(Transform::<[u8; 4], [u8; 4]>::from_ptr(raw).is_none());
+        let rgb = Transform::<[u8; 3], [u8; 3]>::from_ptr(raw).unwrap();
+        cmsCloseProfile(srgb);
+        cmsCloseProfile(lab);
+
+        let mut out = [CIELab::default(); 2];
+        to_lab.transform(&[[255, 255, 255], [0, 0, 0]], &mut out);
+        assert!((out[0].L - 100.).abs() < 0.1 && out[1].L.abs() < 0.1, "{out:?}");
+
+        let mut back = [[0u16, 0, 0, 1234]; 2];
+        from_lab.transform(&out, &mut back);
+        assert!(back[0][..3].iter().all(|&v| v > 65400) && back[1][..3].iter().all(|&v| v < 100), "{back:?}");
+
+        let mut pixels = [[10u8, 200, 30, 77]; 3];
+        rgba.transform_in_place(&mut pixels);
+        assert!(pixels.iter().all(|p| p[3] == 77 && p[1].abs_diff(200) < 2), "{pixels:?}");
+        assert!(format!("{rgba:?}").contains("input_format"));
+        let mut out = [[0u8; 3]];
+        rgb.transform(&[[1, 2, 3]], &mut out);
+        assert_eq!([[1, 2, 3]], out);
+    }
+}
@@ -0,0 +1,293 @@
+//! Shared transforms, reused instead of creating the same transform again.
+
+use super::{Flags, Pixel, Transform, TransformError};
+use crate::ffi::*;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ptr::{self, NonNull};
+use std::sync::{Arc, Mutex};
+
+/// What makes two transforms the same
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+struct Key {
+    input: [u8; 16],
+    output: [u8; 16],
+    intent: u32,
+    flags: u32,
+    /// Bits of the `f64`
+    adaptation_state: u64,
+    input_format: u32,
+    output_format: u32,
+    /// `(In, Out)`, since pixel types can share a format
+    pixels: TypeId,
+}
+
+impl Key {
+    fn same_link(&self, other: &Self) -> bool {
+        let unit = TypeId::of::<()>();
+        Self { input_format: 0, output_format: 0, pixels: unit, ..*self } == Self { input_format: 0, output_format: 0, pixels: unit, ..*other }
+    }
+}
+
+type Shared = Arc<dyn Any + Send + Sync>;
+
+struct Entry {
+    transform: Shared,
+    /// Gives back the transform if nothing else uses it
+    reclaim: fn(Shared) -> Result<HTRANSFORM, Shared>,
+    /// Wraps a reclaimed transform again
+    restore: unsafe fn(HTRANSFORM) -> Shared,
+    last_used: u64,
+}
+
+struct Inner {
+    entries: HashMap<Key, Entry>,
+    clock: u64,
+}
+
+/// Least-recently-used cache of [`Transform`]s, keyed by profile IDs, pixel formats, intent, flags, and adaptation state.
+///
+/// When formats change, an unused transform of the same profiles is converted with `cmsChangeBuffersFormat`
+/// instead of creating a new one. LCMS only allows this for transforms that don't take 8-bit input.
+pub struct TransformCache {
+    context: Context,
+    capacity: usize,
+    inner: Mutex<Inner>,
+}
+
+// The context is only passed to LCMS, which locks it when needed
+unsafe impl Send for TransformCache {}
+unsafe impl Sync for TransformCache {}
+
+impl TransformCache {
+    /// Keeps up to `capacity` transforms
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        unsafe { Self::new_thr(ptr::null_mut(), capacity) }
+    }
+
+    /// Same as [`TransformCache::new`], creating transforms in the `context`
+    ///
+    /// # Safety
+    ///
+    /// `context` must be valid or null, and outlive the cache and its transforms.
+    #[must_use]
+    pub unsafe fn new_thr(context: Context, capacity: usize) -> Self {
+        Self {
+            context,
+            capacity,
+            inner: Mutex::new(Inner { entries: HashMap::new(), clock: 0 }),
+        }
+    }
+
+    /// Cached or new transform, with the context's adaptation state
+    ///
+    /// Fails if `flags` don't pass [`Flags::validate`], or LCMS can't create the transform or the profiles' IDs.
+    ///
+    /// # Safety
+    ///
+    /// Profiles must be valid. Profiles without an ID get it computed and set in their header (`cmsMD5computeID`).
+    pub unsafe fn get<In: Pixel, Out: Pixel>(&self, input: HPROFILE, output: HPROFILE, intent: impl Into<IntentCode>, flags: Flags) -> Result<Arc<Transform<In, Out>>, TransformError> {
+        self.get_with_adaptation(input, output, intent, flags, cmsSetAdaptationStateTHR(self.context, -1.))
+    }
+
+    /// Cached or new transform with the given observer adaptation state
+    ///
+    /// # Safety
+    ///
+    /// Same as [`TransformCache::get`].
+    pub unsafe fn get_with_adaptation<In: Pixel, Out: Pixel>(&self, input: HPROFILE, output: HPROFILE, intent: impl Into<IntentCode>, flags: Flags,
+        adaptation_state: f64) -> Result<Arc<Transform<In, Out>>, TransformError> {
+        flags.validate().map_err(TransformError::Flags)?;
+        let intent = intent.into().0;
+        let key = Key {
+            input: profile_id(input).ok_or(TransformError::Failed)?,
+            output: profile_id(output).ok_or(TransformError::Failed)?,
+            intent,
+            flags: flags.bits(),
+            adaptation_state: adaptation_state.to_bits(),
+            input_format: In::FORMAT.0,
+            output_format: Out::FORMAT.0,
+            pixels: TypeId::of::<(In, Out)>(),
+        };
+
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.clock += 1;
+        let now = inner.clock;
+        if let Some(entry) = inner.entries.get_mut(&key) {
+            entry.last_used = now;
+            return Ok(entry.transform.clone().downcast().expect("key has the pixel types"));
+        }
+
+        let transform = self.reformat(&mut inner, &key).or_else(|| {
+            let mut profiles = [input, output];
+            let bpc = Bool::from(flags.contains(Flags::BLACK_POINT_COMPENSATION));
+            let mut bpcs = [bpc; 2];
+            let mut intents = [intent; 2];
+            let mut adaptations = [adaptation_state; 2];
+            Transform::from_ptr(cmsCreateExtendedTransform(self.context, 2, profiles.as_mut_ptr(), bpcs.as_mut_ptr(), intents.as_mut_ptr(),
+                adaptations.as_mut_ptr(), ptr::null_mut(), 0, In::FORMAT, Out::FORMAT, flags.bits()))
+        }).ok_or(TransformError::Failed)?;
+        let transform = Arc::new(transform);
+
+        if self.capacity > 0 {
+            if inner.entries.len() >= self.capacity {
+                let oldest = inner.entries.iter().min_by_key(|(_, e)| e.last_used).map(|(k, _)| *k);
+                if let Some(oldest) = oldest {
+                    inner.entries.remove(&oldest);
+                }
+            }
+            inner.entries.insert(key, Entry {
+                transform: transform.clone(),
+                reclaim: reclaim::<In, Out>,
+                restore: restore::<In, Out>,
+                last_used: now,
+            });
+        }
+        Ok(transform)
+    }
+
+    /// Takes an unused transform of the same profiles and settings, and changes its formats
+    unsafe fn reformat<In: Pixel, Out: Pixel>(&self, inner: &mut Inner, key: &Key) -> Option<Transform<In, Out>> {
+        // Float formats would be converted through the 16-bit pipeline, unlike a new float transform
+        if In::FORMAT.float() || Out::FORMAT.float() {
+            return None;
+        }
+        // LCMS sets `FLAGS_CAN_CHANGE_FORMATTER` only on transforms created for 16-bit input
+        let old_key = *inner.entries.iter()
+            .find(|(k, e)| k.same_link(key) && PixelFormat(k.input_format).bytes_per_channel() != 1 && Arc::strong_count(&e.transform) == 1)?.0;
+        let entry = inner.entries.remove(&old_key)?;
+        match (entry.reclaim)(entry.transform) {
+            Ok(xform) => {
+                if cmsChangeBuffersFormat(xform, In::FORMAT, Out::FORMAT) != 0 {
+                    return Transform::from_ptr(xform);
+                }
+                // Formats are unchanged, so it's still valid for the old key
+                inner.entries.insert(old_key, Entry { transform: (entry.restore)(xform), ..entry });
+                None
+            },
+            Err(transform) => {
+                inner.entries.insert(old_key, Entry { transform, ..entry });
+                None
+            },
+        }
+    }
+
+    /// Number of cached transforms
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).entries.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forgets all transforms. Ones still in use stay valid.
+    pub fn clear(&self) {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).entries.clear();
+    }
+}
+
+impl fmt::Debug for TransformCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransformCache")
+            .field("capacity", &self.capacity)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+fn reclaim<In: Pixel, Out: Pixel>(shared: Shared) -> Result<HTRANSFORM, Shared> {
+    let transform = shared.downcast::<Transform<In, Out>>()?;
+    Arc::try_unwrap(transform).map(Transform::into_ptr).map_err(|t| t as Shared)
+}
+
+/// The transform must have the formats of `In` and `Out`
+unsafe fn restore<In: Pixel, Out: Pixel>(xform: HTRANSFORM) -> Shared {
+    Arc::new(Transform::<In, Out> { xform: NonNull::new_unchecked(xform), _pixels: PhantomData })
+}
+
+unsafe fn profile_id(profile: HPROFILE) -> Option<[u8; 16]> {
+    if profile.is_null() {
+        return None;
+    }
+    let mut id = [0; 16];
+    cmsGetHeaderProfileID(profile, id.as_mut_ptr());
+    if id == [0; 16] {
+        if cmsMD5computeID(profile) == 0 {
+            return None;
+        }
+        cmsGetHeaderProfileID(profile, id.as_mut_ptr());
+    }
+    Some(id)
+}
+
+#[test]
+fn cache_reuse() {
+    unsafe {
+        let srgb = cmsCreate_sRGBProfile();
+        let lab = cmsCreateLab4Profile(ptr::null());
+        let cache = TransformCache::new(2);
+
+        let a = cache.get::<[u8; 3], CIELab>(srgb, lab, Intent::Perceptual, Flags::new()).unwrap();
+        let b = cache.get::<[u8; 3], CIELab>(srgb, lab, Intent::Perceptual, Flags::new()).unwrap();
+        assert!(Arc::ptr_eq(&a, &b));
+        let other_intent = cache.get::<[u8; 3], CIELab>(srgb, lab, Intent::RelativeColorimetric, Flags::new()).unwrap();
+        assert!(!Arc::ptr_eq(&a, &other_intent));
+        let other_state = cache.get_with_adaptation::<[u8; 3], CIELab>(srgb, lab, Intent::Perceptual, Flags::new(), 0.5).unwrap();
+        assert!(!Arc::ptr_eq(&a, &other_state));
+        assert_eq!(2, cache.len());
+        // The first one was evicted, but still works
+        let mut out = [CIELab::default()];
+        a.transform(&[[255; 3]], &mut out);
+        assert!((out[0].L - 100.).abs() < 0.1);
+        drop((a, b, other_intent, other_state));
+
+        // An unused 16-bit transform gets 8-bit output, replacing its entry
+        cache.clear();
+        let rgb16 = cache.get::<[u16; 3], [u16; 3]>(srgb, srgb, Intent::Perceptual, Flags::new()).unwrap();
+        drop(rgb16);
+        let to8 = cache.get::<[u16; 3], [u8; 3]>(srgb, srgb, Intent::Perceptual, Flags::new()).unwrap();
+        assert_eq!(1, cache.len());
+        assert_eq!(PixelFormat::RGB_8, cmsGetTransformOutputFormat(to8.as_ptr()));
+        let mut out = [[0u8; 3]];
+        to8.transform(&[[65535, 0, 1000]], &mut out);
+        assert!(out[0][0] > 250 && out[0][1] < 5, "{out:?}");
+        // In use, so the 16-bit one is created again
+        let rgb16 = cache.get::<[u16; 3], [u16; 3]>(srgb, srgb, Intent::Perceptual, Flags::new()).unwrap();
+        assert_eq!(2, cache.len());
+        drop((to8, rgb16));
+        // LCMS can't change formats of transforms with 8-bit input, so the unused 8-bit one stays cached
+        cache.clear();
+        let rgb8 = cache.get::<[u8; 3], [u8; 3]>(srgb, srgb, Intent::Perceptual, Flags::new()).unwrap();
+        drop(rgb8);
+        cache.get::<[u16; 3], [u16; 3]>(srgb, srgb, Intent::Perceptual, Flags::new()).unwrap();
+        assert_eq!(2, cache.len());
+        let rgb8 = cache.get::<[u8; 3], [u8; 3]>(srgb, srgb, Intent::Perceptual, Flags::new()).unwrap();
+
+        // Same format as `[u8; 3]`, but a different type
+        #[derive(Copy, Clone)]
+        #[repr(transparent)]
+        struct Rgb8([u8; 3]);
+        unsafe impl Pixel for Rgb8 {
+            const FORMAT: PixelFormat = PixelFormat::RGB_8;
+        }
+        let typed = cache.get::<Rgb8, [u8; 3]>(srgb, srgb, Intent::Perceptual, Flags::new()).unwrap();
+        let mut out = [[0u8; 3]];
+        typed.transform(&[Rgb8([1, 2, 3])], &mut out);
+        assert_eq!([[1, 2, 3]], out);
+
+        let shared = Arc::clone(&rgb8);
+        std::thread::spawn(move || {
+            let mut out = [[0u8; 3]];
+            shared.transform(&[[1, 2, 3]], &mut out);
+        }).join().unwrap();
+
+        cmsCloseProfile(lab);
+        cmsCloseProfile(srgb);
+    }
+}
@@ -0,0 +1,257 @@
+//! Multi-profile transforms (`cmsCreateExtendedTransform`) with settings per profile.
+
+use super::{Flags, FlagsError, Pixel, Transform};
+use crate::ffi::*;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ptr;
+
+/// One profile in a [`ChainBuilder`], and how it's linked
+#[derive(Debug, Copy, Clone)]
+pub struct Link {
+    pub profile: HPROFILE,
+    /// Built-in or plug-in intent, e.g. `Intent::Perceptual.into()`
+    pub intent: IntentCode,
+    pub black_point_compensation: bool,
+    /// Observer adaptation state for absolute colorimetric intents, 0..=1. `None` uses the context's state.
+    pub adaptation_state: Option<f64>,
+}
+
+impl Link {
+    /// Perceptual intent without black point compensation
+    #[must_use]
+    pub fn new(profile: HPROFILE) -> Self {
+        Self {
+            profile,
+            intent: Intent::Perceptual.into(),
+            black_point_compensation: false,
+            adaptation_state: None,
+        }
+    }
+}
+
+/// Why a chain of profiles can't be linked
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChainError {
+    /// LCMS supports 1 to 255 profiles
+    ProfileCount(usize),
+    NullProfile(usize),
+    /// The profile of link `link` takes `expected` colour space, but the previous link gives `found`
+    Incompatible { link: usize, expected: ColorSpaceSignature, found: ColorSpaceSignature },
+    /// The input pixel type doesn't fit the colour space of the first link
+    InputFormat(ColorSpaceSignature),
+    /// The output pixel type doesn't fit the colour space of the last link
+    OutputFormat(ColorSpaceSignature),
+    /// Gamut check PCS position must be after the first link, and before the last
+    GamutPosition(usize),
+    Flags(FlagsError),
+    /// Rejected by LCMS for another reason
+    Failed,
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::ProfileCount(n) => write!(f, "{n} profiles given, 1 to 255 expected"),
+            Self::NullProfile(link) => write!(f, "profile of link {link} is null"),
+            Self::Incompatible { link, expected, found } => write!(f, "link {link} takes {expected:?}, but link {} gives {found:?}", link - 1),
+            Self::InputFormat(space) => write!(f, "input pixel format doesn't match {space:?} of the first profile"),
+            Self::OutputFormat(space) => write!(f, "output pixel format doesn't match {space:?} of the last profile"),
+            Self::GamutPosition(pos) => write!(f, "gamut check PCS position {pos} is not between two profiles"),
+            Self::Flags(err) => err.fmt(f),
+            Self::Failed => f.write_str("LCMS failed to create the transform"),
+        }
+    }
+}
+
+impl Error for ChainError {}
+
+/// Creates a [`Transform`] through a chain of profiles, like `cmsCreateExtendedTransform`
+pub struct ChainBuilder<In, Out> {
+    context: Context,
+    links: Vec<Link>,
+    gamut: Option<(HPROFILE, usize)>,
+    flags: Flags,
+    _pixels: PhantomData<fn(&In) -> Out>,
+}
+
+impl<In: Pixel, Out: Pixel> ChainBuilder<In, Out> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            context: ptr::null_mut(),
+            links: Vec::new(),
+            gamut: None,
+            flags: Flags::new(),
+            _pixels: PhantomData,
+        }
+    }
+
+    /// Context to allocate the transform in. It must stay valid until [`ChainBuilder::build`].
+    #[must_use]
+    pub fn context(mut self, context: Context) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Appends a profile to the chain
+    #[must_use]
+    pub fn link(mut self, link: Link) -> Self {
+        self.links.push(link);
+        self
+    }
+
+    /// Marks colours out of `profile`'s gamut with the alarm codes (`FLAGS_GAMUTCHECK`).
+    ///
+    /// The check is done on the PCS after the first `pcs_position` links.
+    #[must_use]
+    pub fn gamut_check(mut self, profile: HPROFILE, pcs_position: usize) -> Self {
+        self.gamut = Some((profile, pcs_position));
+        self
+    }
+
+    /// Flags for the transform. `GAMUT_CHECK` is set by [`ChainBuilder::gamut_check`].
+    #[must_use]
+    pub fn flags(mut self, flags: Flags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Checks the chain the same way LCMS links it, so that errors name the incompatible link.
+    ///
+    /// # Safety
+    ///
+    /// Profiles must be valid or null.
+    pub unsafe fn check(&self) -> Result<(), ChainError> {
+        let count = self.links.len();
+        if count == 0 || count > 255 {
+            return Err(ChainError::ProfileCount(count));
+        }
+        if let Some(i) = self.links.iter().position(|l| l.profile.is_null()) {
+            return Err(ChainError::NullProfile(i));
+        }
+        if self.gamut.is_some() {
+            (self.flags | Flags::GAMUT_CHECK).validate()
+        } else {
+            self.flags.validate()
+        }.map_err(ChainError::Flags)?;
+        if let Some((profile, pos)) = self.gamut {
+            if profile.is_null() || pos == 0 || pos >= count - 1 {
+                return Err(ChainError::GamutPosition(pos));
+            }
+        }
+
+        let entry = cmsGetColorSpace(self.links[0].profile);
+        let mut current = entry;
+        for (i, link) in self.links.iter().enumerate() {
+            let class = cmsGetDeviceClass(link.profile);
+            let is_device_link = class == ProfileClassSignature::LinkClass || class == ProfileClassSignature::AbstractClass;
+            // Profiles are used in the input direction unless the chain is already in the PCS
+            let is_input = if i == 0 {
+                !is_device_link
+            } else {
+                current != ColorSpaceSignature::XYZData && current != ColorSpaceSignature::LabData
+            };
+            let (space_in, space_out) = if is_input || is_device_link {
+                (cmsGetColorSpace(link.profile), cmsGetPCS(link.profile))
+            } else {
+                (cmsGetPCS(link.profile), cmsGetColorSpace(link.profile))
+            };
+            if !is_compatible(space_in, current) {
+                return Err(ChainError::Incompatible { link: i, expected: space_in, found: current });
+            }
+            current = space_out;
+        }
+
+        if !is_proper_color_space(entry, In::FORMAT) {
+            return Err(ChainError::InputFormat(entry));
+        }
+        if !is_proper_color_space(current, Out::FORMAT) {
+            return Err(ChainError::OutputFormat(current));
+        }
+        Ok(())
+    }
+
+    /// # Safety
+    ///
+    /// Profiles and the context must be valid. Profiles can be closed once the transform is created.
+    pub unsafe fn build(self) -> Result<Transform<In, Out>, ChainError> {
+        self.check()?;
+        let context_state = cmsSetAdaptationStateTHR(self.context, -1.);
+        let mut profiles: Vec<_> = self.links.iter().map(|l| l.profile).collect();
+        let mut bpc: Vec<_> = self.links.iter().map(|l| Bool::from(l.black_point_compensation)).collect();
+        let mut intents: Vec<_> = self.links.iter().map(|l| l.intent.0).collect();
+        let mut adaptation: Vec<_> = self.links.iter().map(|l| l.adaptation_state.unwrap_or(context_state)).collect();
+        let (gamut, pcs_position) = self.gamut.unwrap_or((ptr::null_mut(), 0));
+        let flags = if gamut.is_null() { self.flags.without(Flags::GAMUT_CHECK) } else { self.flags | Flags::GAMUT_CHECK }.bits();
+
+        let xform = cmsCreateExtendedTransform(self.context, profiles.len() as u32, profiles.as_mut_ptr(), bpc.as_mut_ptr(),
+            intents.as_mut_ptr(), adaptation.as_mut_ptr(), gamut, pcs_position as u32, In::FORMAT, Out::FORMAT, flags);
+        Transform::from_ptr(xform).ok_or(ChainError::Failed)
+    }
+}
+
+impl<In: Pixel, Out: Pixel> Default for ChainBuilder<In, Out> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Same as LCMS's `ColorSpaceIsCompatible`
+fn is_compatible(a: ColorSpaceSignature, b: ColorSpaceSignature) -> bool {
+    use ColorSpaceSignature::*;
+    a == b || matches!((a, b), (Sig4colorData, CmykData) | (CmykData, Sig4colorData) | (XYZData, LabData) | (LabData, XYZData))
+}
+
+/// Same as LCMS's `IsProperColorSpace`
+fn is_proper_color_space(space: ColorSpaceSignature, format: PixelFormat) -> bool {
+    let format_space = format.pixel_type();
+    let profile_space = PixelType(unsafe { _cmsLCMScolorSpace(space) } as u32);
+    if format_space == PT_ANY {
+        return format.channels() == unsafe { cmsChannelsOf(space) } as usize;
+    }
+    format_space == profile_space
+        || (format_space == PT_LabV2 && profile_space == PT_Lab)
+        || (format_space == PT_Lab && profile_space == PT_LabV2)
+}
+
+#[test]
+fn chain_with_per_link_settings() {
+    unsafe {
+        let srgb = cmsCreate_sRGBProfile();
+        let lab = cmsCreateLab4Profile(ptr::null());
+        let gamma = cmsBuildGamma(ptr::null_mut(), 1.8);
+        let gray = cmsCreateGrayProfile(CIExyY::d50(), gamma);
+        cmsFreeToneCurve(gamma);
+
+        let to_gray = ChainBuilder::<[u8; 3], [u8; 1]>::new()
+            .link(Link { black_point_compensation: true, ..Link::new(srgb) })
+            .link(Link::new(lab))
+            .link(Link { intent: Intent::RelativeColorimetric.into(), adaptation_state: Some(0.), ..Link::new(lab) })
+            .link(Link::new(gray))
+            .build()
+            .unwrap();
+        let mut out = [[0u8]; 2];
+        to_gray.transform(&[[255, 255, 255], [0, 0, 0]], &mut out);
+        assert!(out[0][0] > 250 && out[1][0] < 5, "{out:?}");
+
+        // Lab pixels don't fit the RGB profile
+        let err = ChainBuilder::<CIELab, [u8; 3]>::new().link(Link::new(srgb)).link(Link::new(srgb)).check().unwrap_err();
+        assert_eq!(ChainError::InputFormat(ColorSpaceSignature::RgbData), err);
+        // An RGB profile can't follow the gray device space
+        let err = ChainBuilder::<[u8; 3], [u8; 3]>::new()
+            .link(Link::new(srgb)).link(Link::new(gray)).link(Link::new(srgb))
+            .build().unwrap_err();
+        assert_eq!("link 2 takes RgbData, but link 1 gives GrayData", err.to_string());
+        assert_eq!(ChainError::ProfileCount(0), ChainBuilder::<[u8; 3], [u8; 3]>::new().check().unwrap_err());
+        let err = ChainBuilder::<[u8; 3], [u8; 3]>::new().link(Link::new(srgb)).link(Link::new(srgb)).gamut_check(gray, 1).check().unwrap_err();
+        assert_eq!(ChainError::GamutPosition(1), err);
+        let err = ChainBuilder::<[u8; 3], [u8; 3]>::new().link(Link::new(srgb)).flags(Flags::NO_OPTIMIZE.force_clut()).check().unwrap_err();
+        assert_eq!(ChainError::Flags(FlagsError::Conflict(Flags::NO_OPTIMIZE, Flags::FORCE_CLUT)), err);
+
+        cmsCloseProfile(gray);
+        cmsCloseProfile(lab);
+        cmsCloseProfile(srgb);
+    }
+}
@@ -0,0 +1,306 @@
+//! Typed `FLAGS_*` for creating transforms.
+
+use crate::ffi::*;
+use std::error::Error;
+use std::fmt;
+use std::ops::{BitOr, BitOrAssign};
+
+/// Set of `FLAGS_*` bits, and the number of grid points of precalculated tables
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Flags(u32);
+
+const GRID_POINTS_MASK: u32 = 0xFF << 16;
+
+const NAMES: [(u32, &str); 19] = [
+    (FLAGS_CLUT_POST_LINEARIZATION, "CLUT_POST_LINEARIZATION"),
+    (FLAGS_FORCE_CLUT, "FORCE_CLUT"),
+    (FLAGS_NOWHITEONWHITEFIXUP, "NO_WHITE_ON_WHITE_FIXUP"),
+    (FLAGS_8BITS_DEVICELINK, "DEVICELINK_8BITS"),
+    (FLAGS_CLUT_PRE_LINEARIZATION, "CLUT_PRE_LINEARIZATION"),
+    (FLAGS_GUESSDEVICECLASS, "GUESS_DEVICE_CLASS"),
+    (FLAGS_NOCACHE, "NO_CACHE"),
+    (FLAGS_KEEP_SEQUENCE, "KEEP_SEQUENCE"),
+    (FLAGS_NOOPTIMIZE, "NO_OPTIMIZE"),
+    (FLAGS_NULLTRANSFORM, "NULL_TRANSFORM"),
+    (FLAGS_HIGHRESPRECALC, "HIGH_RES_PRECALC"),
+    (FLAGS_LOWRESPRECALC, "LOW_RES_PRECALC"),
+    (FLAGS_GAMUTCHECK, "GAMUT_CHECK"),
+    (FLAGS_BLACKPOINTCOMPENSATION, "BLACK_POINT_COMPENSATION"),
+    (FLAGS_SOFTPROOFING, "SOFT_PROOFING"),
+    (FLAGS_NONEGATIVES, "NO_NEGATIVES"),
+    (FLAGS_NODEFAULTRESOURCEDEF, "NO_DEFAULT_RESOURCE_DEF"),
+    (FLAGS_CAN_CHANGE_FORMATTER, "CAN_CHANGE_FORMATTER"),
+    (FLAGS_COPY_ALPHA, "COPY_ALPHA"),
+];
+
+impl Flags {
+    pub const NO_CACHE: Self = Self(FLAGS_NOCACHE);
+    pub const NO_OPTIMIZE: Self = Self(FLAGS_NOOPTIMIZE);
+    pub const NULL_TRANSFORM: Self = Self(FLAGS_NULLTRANSFORM);
+    pub const GAMUT_CHECK: Self = Self(FLAGS_GAMUTCHECK);
+    pub const SOFT_PROOFING: Self = Self(FLAGS_SOFTPROOFING);
+    pub const BLACK_POINT_COMPENSATION: Self = Self(FLAGS_BLACKPOINTCOMPENSATION);
+    pub const NO_WHITE_ON_WHITE_FIXUP: Self = Self(FLAGS_NOWHITEONWHITEFIXUP);
+    pub const HIGH_RES_PRECALC: Self = Self(FLAGS_HIGHRESPRECALC);
+    pub const LOW_RES_PRECALC: Self = Self(FLAGS_LOWRESPRECALC);
+    pub const DEVICELINK_8BITS: Self = Self(FLAGS_8BITS_DEVICELINK);
+    pub const GUESS_DEVICE_CLASS: Self = Self(FLAGS_GUESSDEVICECLASS);
+    pub const KEEP_SEQUENCE: Self = Self(FLAGS_KEEP_SEQUENCE);
+    pub const FORCE_CLUT: Self = Self(FLAGS_FORCE_CLUT);
+    pub const CLUT_POST_LINEARIZATION: Self = Self(FLAGS_CLUT_POST_LINEARIZATION);
+    pub const CLUT_PRE_LINEARIZATION: Self = Self(FLAGS_CLUT_PRE_LINEARIZATION);
+    pub const NO_NEGATIVES: Self = Self(FLAGS_NONEGATIVES);
+    pub const COPY_ALPHA: Self = Self(FLAGS_COPY_ALPHA);
+    pub const NO_DEFAULT_RESOURCE_DEF: Self = Self(FLAGS_NODEFAULTRESOURCEDEF);
+    pub const CAN_CHANGE_FORMATTER: Self = Self(FLAGS_CAN_CHANGE_FORMATTER);
+
+    /// No flags
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Checks raw `FLAGS_*` bits, e.g. from C code
+    pub fn from_bits(bits: u32) -> Result<Self, FlagsError> {
+        let known = NAMES.iter().fold(GRID_POINTS_MASK, |all, &(bit, _)| all | bit);
+        if bits & !known != 0 {
+            return Err(FlagsError::UnknownBits(bits & !known));
+        }
+        let flags = Self(bits);
+        flags.validate()?;
+        Ok(flags)
+    }
+
+    /// Value for LCMS functions
+    #[must_use]
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Whether all flags of `other` are set (grid points are ignored)
+    #[must_use]
+    pub const fn contains(self, other: Self) -> bool {
+        let other = other.0 & !GRID_POINTS_MASK;
+        self.0 & other == other
+    }
+
+    /// Removes flags of `other`
+    #[must_use]
+    pub const fn without(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    /// `FLAGS_NOCACHE`: don't cache the last pixel
+    #[must_use]
+    pub const fn no_cache(self) -> Self {
+        Self(self.0 | FLAGS_NOCACHE)
+    }
+
+    /// `FLAGS_NOOPTIMIZE`: evaluate the full pipeline for every pixel
+    #[must_use]
+    pub const fn no_optimize(self) -> Self {
+        Self(self.0 | FLAGS_NOOPTIMIZE)
+    }
+
+    /// `FLAGS_NULLTRANSFORM`: only convert between pixel formats
+    #[must_use]
+    pub const fn null_transform(self) -> Self {
+        Self(self.0 | FLAGS_NULLTRANSFORM)
+    }
+
+    /// `FLAGS_GAMUTCHECK`
+    #[must_use]
+    pub const fn gamut_check(self) -> Self {
+        Self(self.0 | FLAGS_GAMUTCHECK)
+    }
+
+    /// `FLAGS_SOFTPROOFING`
+    #[must_use]
+    pub const fn soft_proofing(self) -> Self {
+        Self(self.0 | FLAGS_SOFTPROOFING)
+    }
+
+    /// `FLAGS_BLACKPOINTCOMPENSATION`
+    #[must_use]
+    pub const fn black_point_compensation(self) -> Self {
+        Self(self.0 | FLAGS_BLACKPOINTCOMPENSATION)
+    }
+
+    /// `FLAGS_NOWHITEONWHITEFIXUP`: don't fix the white point of precalculated tables
+    #[must_use]
+    pub const fn no_white_on_white_fixup(self) -> Self {
+        Self(self.0 | FLAGS_NOWHITEONWHITEFIXUP)
+    }
+
+    /// `FLAGS_HIGHRESPRECALC`: more grid points, more memory
+    #[must_use]
+    pub const fn high_res_precalc(self) -> Self {
+        Self(self.0 | FLAGS_HIGHRESPRECALC)
+    }
+
+    /// `FLAGS_LOWRESPRECALC`: fewer grid points, less memory
+    #[must_use]
+    pub const fn low_res_precalc(self) -> Self {
+        Self(self.0 | FLAGS_LOWRESPRECALC)
+    }
+
+    /// `FLAGS_FORCE_CLUT`: always optimize into a CLUT
+    #[must_use]
+    pub const fn force_clut(self) -> Self {
+        Self(self.0 | FLAGS_FORCE_CLUT)
+    }
+
+    /// `FLAGS_CLUT_PRE_LINEARIZATION`
+    #[must_use]
+    pub const fn clut_pre_linearization(self) -> Self {
+        Self(self.0 | FLAGS_CLUT_PRE_LINEARIZATION)
+    }
+
+    /// `FLAGS_CLUT_POST_LINEARIZATION`
+    #[must_use]
+    pub const fn clut_post_linearization(self) -> Self {
+        Self(self.0 | FLAGS_CLUT_POST_LINEARIZATION)
+    }
+
+    /// `FLAGS_NONEGATIVES`: clip negative values of float transforms
+    #[must_use]
+    pub const fn no_negatives(self) -> Self {
+        Self(self.0 | FLAGS_NONEGATIVES)
+    }
+
+    /// `FLAGS_COPY_ALPHA`: copy extra channels from input to output
+    #[must_use]
+    pub const fn copy_alpha(self) -> Self {
+        Self(self.0 | FLAGS_COPY_ALPHA)
+    }
+
+    /// `FLAGS_GRIDPOINTS(n)`: number of grid points of precalculated tables. 0 lets LCMS choose.
+    #[must_use]
+    pub const fn with_grid_points(self, n: u8) -> Self {
+        Self(self.0 & !GRID_POINTS_MASK | (n as u32) << 16)
+    }
+
+    /// Grid points set by [`Flags::with_grid_points`]
+    #[must_use]
+    pub const fn grid_points(self) -> Option<u8> {
+        match (self.0 >> 16) as u8 {
+            0 => None,
+            n => Some(n),
+        }
+    }
+
+    /// Rejects combinations of flags that contradict each other, and would silently leave some of them ignored
+    pub fn validate(self) -> Result<(), FlagsError> {
+        let conflicts = [
+            (Self::HIGH_RES_PRECALC, Self::LOW_RES_PRECALC),
+            (Self::NULL_TRANSFORM, Self::GAMUT_CHECK),
+            (Self::NULL_TRANSFORM, Self::SOFT_PROOFING),
+            (Self::NULL_TRANSFORM, Self::BLACK_POINT_COMPENSATION),
+            (Self::NO_OPTIMIZE, Self::FORCE_CLUT),
+        ];
+        for (a, b) in conflicts {
+            if self.contains(a) && self.contains(b) {
+                return Err(FlagsError::Conflict(a, b));
+            }
+        }
+        if let Some(n) = self.grid_points() {
+            if n < 2 {
+                return Err(FlagsError::GridPoints(n));
+            }
+            // Explicit grid points override the precalculation resolution
+            for res in [Self::HIGH_RES_PRECALC, Self::LOW_RES_PRECALC] {
+                if self.contains(res) {
+                    return Err(FlagsError::Conflict(Self::new().with_grid_points(n), res));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl BitOr for Flags {
+    type Output = Self;
+
+    /// Grid points of `rhs` win, if set
+    fn bitor(self, rhs: Self) -> Self {
+        let grid = if rhs.0 & GRID_POINTS_MASK != 0 { rhs.0 & GRID_POINTS_MASK } else { self.0 & GRID_POINTS_MASK };
+        Self((self.0 | rhs.0) & !GRID_POINTS_MASK | grid)
+    }
+}
+
+impl BitOrAssign for Flags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        *self = *self | rhs;
+    }
+}
+
+impl fmt::Debug for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Flags(")?;
+        let mut first = true;
+        let mut sep = |f: &mut fmt::Formatter<'_>| {
+            let s = if first { "" } else { " | " };
+            first = false;
+            f.write_str(s)
+        };
+        for &(bit, name) in &NAMES {
+            if self.0 & bit != 0 {
+                sep(f)?;
+                f.write_str(name)?;
+            }
+        }
+        if let Some(n) = self.grid_points() {
+            sep(f)?;
+            write!(f, "GRID_POINTS({n})")?;
+        }
+        let unknown = NAMES.iter().fold(self.0 & !GRID_POINTS_MASK, |rest, &(bit, _)| rest & !bit);
+        if unknown != 0 {
+            sep(f)?;
+            write!(f, "{unknown:#x}")?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// Flags rejected by [`Flags::validate`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FlagsError {
+    /// These flags can't be used together
+    Conflict(Flags, Flags),
+    /// Precalculated tables need at least 2 grid points
+    GridPoints(u8),
+    /// Bits that aren't `FLAGS_*`
+    UnknownBits(u32),
+}
+
+impl fmt::Display for FlagsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Conflict(a, b) => write!(f, "{a:?} contradicts {b:?}"),
+            Self::GridPoints(n) => write!(f, "{n} grid points is too few"),
+            Self::UnknownBits(bits) => write!(f, "unknown flags {bits:#x}"),
+        }
+    }
+}
+
+impl Error for FlagsError {}
+
+#[test]
+fn flags() {
+    let flags = Flags::new().black_point_compensation().copy_alpha().with_grid_points(33);
+    assert_eq!(FLAGS_BLACKPOINTCOMPENSATION | FLAGS_COPY_ALPHA | FLAGS_GRIDPOINTS(33), flags.bits());
+    assert_eq!(Some(33), flags.grid_points());
+    assert_eq!(Ok(flags), Flags::from_bits(flags.bits()));
+    assert_eq!("Flags(BLACK_POINT_COMPENSATION | COPY_ALPHA | GRID_POINTS(33))", format!("{flags:?}"));
+    assert_eq!("Flags()", format!("{:?}", Flags::default()));
+    assert!(flags.contains(Flags::COPY_ALPHA) && !flags.contains(Flags::NO_CACHE));
+    assert_eq!(Some(17), (flags | Flags::NO_CACHE.with_grid_points(17)).grid_points());
+    assert_eq!(Ok(()), flags.without(Flags::COPY_ALPHA).validate());
+
+    assert_eq!(Err(FlagsError::Conflict(Flags::HIGH_RES_PRECALC, Flags::LOW_RES_PRECALC)), Flags::from_bits(FLAGS_HIGHRESPRECALC | FLAGS_LOWRESPRECALC));
+    let err = (Flags::NULL_TRANSFORM | Flags::GAMUT_CHECK).validate().unwrap_err();
+    assert_eq!("Flags(NULL_TRANSFORM) contradicts Flags(GAMUT_CHECK)", err.to_string());
+    assert!(flags.high_res_precalc().validate().is_err());
+    assert_eq!(Err(FlagsError::GridPoints(1)), Flags::new().with_grid_points(1).validate());
+    assert_eq!(Err(FlagsError::UnknownBits(0x8000_0000)), Flags::from_bits(0x8000_0000));
+}
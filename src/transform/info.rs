@@ -0,0 +1,192 @@
+//! What a transform does, as far as LCMS keeps it, and its export as a device link.
+//!
+//! LCMS doesn't keep the flags a transform was created with, and keeps the profiles only with `FLAGS_KEEP_SEQUENCE`.
+
+use super::{Flags, Pixel, Transform};
+use crate::ffi::*;
+use crate::pipeline::Lut;
+use std::os::raw::{c_char, c_void};
+use std::ptr;
+
+// The `Intent` returned by `cmsGetHeaderRenderingIntent` can be a code of a plug-in intent, which the enum can't hold
+#[allow(clashing_extern_declarations)]
+extern "C" {
+    #[link_name = "cmsGetHeaderRenderingIntent"]
+    fn cmsGetHeaderRenderingIntentRaw(profile: HPROFILE) -> u32;
+}
+
+/// Snapshot of a [`Transform`], e.g. to record which conversion was used
+#[derive(Debug)]
+pub struct TransformInfo {
+    pub input_format: PixelFormat,
+    pub output_format: PixelFormat,
+    pub input_space: ColorSpaceSignature,
+    pub output_space: ColorSpaceSignature,
+    /// Intent of the last profile in the chain. Can be a plug-in intent.
+    pub intent: IntentCode,
+    /// Profiles in the chain. Empty unless the transform was created with [`Flags::KEEP_SEQUENCE`].
+    pub profiles: Vec<LinkedProfile>,
+    /// Copy of the transform's pipeline, as exported to a device link.
+    /// Optimized stages that an ICC profile can't store are resampled into a CLUT.
+    pub pipeline: Lut,
+}
+
+/// Description of a profile in the transform's chain (`PSEQDESC`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkedProfile {
+    /// MD5 profile ID. All zeros if the profile had none.
+    pub id: [u8; 16],
+    pub device_manufacturer: Signature,
+    pub device_model: Signature,
+    pub attributes: u64,
+    pub manufacturer: String,
+    pub model: String,
+    pub description: String,
+}
+
+impl<In: Pixel, Out: Pixel> Transform<In, Out> {
+    /// Formats, colour spaces, intent, profiles and pipeline of the transform.
+    ///
+    /// `None` if it can't be exported as a device link, e.g. for a null transform.
+    #[must_use]
+    pub fn info(&self) -> Option<TransformInfo> {
+        unsafe {
+            let link = DeviceLink(cmsTransform2DeviceLink(self.as_ptr(), 4.3, 0));
+            if link.0.is_null() {
+                return None;
+            }
+            let pipeline = cmsReadTag(link.0, TagSignature::AToB0Tag).cast::<Pipeline>();
+            if pipeline.is_null() {
+                return None;
+            }
+            let sequence = cmsReadTag(link.0, TagSignature::ProfileSequenceDescTag).cast::<SEQ>();
+            let profiles = if sequence.is_null() || (*sequence).seq.is_null() {
+                Vec::new()
+            } else {
+                std::slice::from_raw_parts((*sequence).seq, (*sequence).n as usize).iter().map(|desc| LinkedProfile {
+                    id: id_bytes(desc.ProfileID),
+                    device_manufacturer: desc.deviceMfg,
+                    device_model: desc.deviceModel,
+                    attributes: desc.attributes,
+                    manufacturer: mlu_string(desc.Manufacturer),
+                    model: mlu_string(desc.Model),
+                    description: mlu_string(desc.Description),
+                }).collect()
+            };
+            Some(TransformInfo {
+                input_format: cmsGetTransformInputFormat(self.as_ptr()),
+                output_format: cmsGetTransformOutputFormat(self.as_ptr()),
+                input_space: cmsGetColorSpace(link.0),
+                output_space: cmsGetPCS(link.0),
+                intent: IntentCode(cmsGetHeaderRenderingIntentRaw(link.0)),
+                profiles,
+                pipeline: Lut::from_ptr(cmsPipelineDup(pipeline))?,
+            })
+        }
+    }
+
+    /// Saves the transform as an ICC device link profile (`cmsTransform2DeviceLink`).
+    ///
+    /// `version` is the ICC version, e.g. `4.3`. Relevant flags are [`Flags::GUESS_DEVICE_CLASS`] and [`Flags::FORCE_CLUT`].
+    /// The profile describes the chain of profiles if the transform was created with [`Flags::KEEP_SEQUENCE`].
+    #[must_use]
+    pub fn to_device_link(&self, version: f64, flags: Flags) -> Option<Vec<u8>> {
+        unsafe {
+            let link = DeviceLink(cmsTransform2DeviceLink(self.as_ptr(), version, flags.bits()));
+            if link.0.is_null() {
+                return None;
+            }
+            let mut len = 0;
+            if cmsSaveProfileToMem(link.0, ptr::null_mut(), &mut len) == 0 {
+                return None;
+            }
+            let mut data = vec![0u8; len as usize];
+            if cmsSaveProfileToMem(link.0, data.as_mut_ptr().cast(), &mut len) == 0 {
+                return None;
+            }
+            data.truncate(len as usize);
+            Some(data)
+        }
+    }
+
+    /// Private data of the transform plugin that took over this transform (`_cmsGetTransformUserData`).
+    /// Null for transforms handled by LCMS.
+    #[must_use]
+    pub fn user_data(&self) -> *mut c_void {
+        unsafe { _cmsGetTransformUserData(self.as_ptr()) }
+    }
+}
+
+/// Closes the profile when dropped
+struct DeviceLink(HPROFILE);
+
+impl Drop for DeviceLink {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            unsafe { cmsCloseProfile(self.0); }
+        }
+    }
+}
+
+/// LCMS copies the ID's bytes into the `u32`s
+fn id_bytes(id: ProfileID) -> [u8; 16] {
+    let mut bytes = [0; 16];
+    for (b, word) in bytes.chunks_exact_mut(4).zip(id.ID32) {
+        b.copy_from_slice(&word.to_ne_bytes());
+    }
+    bytes
+}
+
+unsafe fn mlu_string(mlu: *const MLU) -> String {
+    if mlu.is_null() {
+        return String::new();
+    }
+    let (lang, country) = (b"en\0".as_ptr().cast::<c_char>(), b"US\0".as_ptr().cast::<c_char>());
+    let len = cmsMLUgetUTF8(mlu, lang, country, ptr::null_mut(), 0);
+    let mut buf = vec![0u8; len as usize];
+    cmsMLUgetUTF8(mlu, lang, country, buf.as_mut_ptr().cast(), len);
+    let end = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    buf.truncate(end);
+    String::from_utf8_lossy(&buf).into_owned()
+}
+
+#[test]
+fn info_and_device_link() {
+    unsafe {
+        let srgb = cmsCreate_sRGBProfile();
+        let lab = cmsCreateLab4Profile(ptr::null());
+
+        let xform = Transform::<[u16; 3], CIELab>::new(srgb, lab, Intent::RelativeColorimetric, Flags::KEEP_SEQUENCE).unwrap();
+        let info = xform.info().unwrap();
+        assert_eq!(PixelFormat::RGB_16, info.input_format);
+        assert_eq!(ColorSpaceSignature::RgbData, info.input_space);
+        assert_eq!(ColorSpaceSignature::LabData, info.output_space);
+        assert_eq!(Some(Intent::RelativeColorimetric), info.intent.intent());
+        assert_eq!(2, info.profiles.len());
+        assert!(info.profiles[0].description.contains("sRGB"), "{:?}", info.profiles);
+        assert_eq!((3, 3), (info.pipeline.inputs(), info.pipeline.outputs()));
+        let mut out = [0f32; 3];
+        info.pipeline.eval_f32(&[1., 1., 1.], &mut out);
+        assert!((out[0] - 1.).abs() < 0.01, "{out:?}");
+        assert!(xform.user_data().is_null());
+
+        let icc = xform.to_device_link(4.3, Flags::new()).unwrap();
+        let link = cmsOpenProfileFromMem(icc.as_ptr().cast(), icc.len() as u32);
+        assert!(!link.is_null());
+        assert_eq!(ProfileClassSignature::LinkClass, cmsGetDeviceClass(link));
+        assert!(!cmsReadTag(link, TagSignature::ProfileSequenceDescTag).is_null());
+        let relinked = Transform::<[u16; 3], CIELab>::new(link, ptr::null_mut(), Intent::RelativeColorimetric, Flags::new()).unwrap();
+        let (mut a, mut b) = ([CIELab::default()], [CIELab::default()]);
+        xform.transform(&[[65535, 0, 0]], &mut a);
+        relinked.transform(&[[65535, 0, 0]], &mut b);
+        assert!((a[0].a - b[0].a).abs() < 1., "{a:?} {b:?}");
+        cmsCloseProfile(link);
+
+        // Without the flag, LCMS forgets the profiles
+        let xform = Transform::<[u8; 3], CIELab>::new(srgb, lab, Intent::Perceptual, Flags::new()).unwrap();
+        assert!(xform.info().unwrap().profiles.is_empty());
+
+        cmsCloseProfile(lab);
+        cmsCloseProfile(srgb);
+    }
+}
@@ -0,0 +1,222 @@
+//! Named colour (spot colour) profiles, looked up by colour name or index.
+
+use super::{Pixel, Transform};
+use crate::ffi::*;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+
+/// Index of a colour in a named colour list (`NAMED_COLOR_INDEX`)
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct NamedIndex(pub u16);
+
+unsafe impl Pixel for NamedIndex {
+    const FORMAT: PixelFormat = PixelFormat::NAMED_COLOR_INDEX;
+}
+
+/// A colour of a [`NamedColors`] profile
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedColor<D> {
+    pub index: NamedIndex,
+    /// Name without the prefix and suffix common to the whole list
+    pub name: String,
+    pub prefix: String,
+    pub suffix: String,
+    /// PCS value, in D50 Lab
+    pub lab: CIELab,
+    /// Device colorants, e.g. [`Cmyk<u16>`](super::Cmyk) for a CMYK profile
+    pub device: D,
+}
+
+impl<D> NamedColor<D> {
+    /// Name with the list's prefix and suffix, e.g. `"PANTONE 185 C"`
+    #[must_use]
+    pub fn full_name(&self) -> String {
+        [&self.prefix, &self.name, &self.suffix].iter().filter(|s| !s.is_empty()).map(|s| s.as_str()).collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// Transforms of a named colour profile to Lab and to its device colorants `D`
+///
+/// `D` must fit the profile's colour space, e.g. `Cmyk<u16>` for a CMYK spot colour library.
+pub struct NamedColors<D> {
+    to_lab: Transform<NamedIndex, CIELab>,
+    to_device: Transform<NamedIndex, D>,
+}
+
+impl<D: Pixel> NamedColors<D> {
+    /// `None` if the profile isn't a named colour profile, or `D` doesn't fit its colour space.
+    ///
+    /// # Safety
+    ///
+    /// The profile must be valid. It can be closed afterwards.
+    #[must_use]
+    pub unsafe fn new(profile: HPROFILE, intent: impl Into<IntentCode>) -> Option<Self> {
+        Self::new_thr(ptr::null_mut(), profile, intent)
+    }
+
+    /// Same as [`NamedColors::new`], allocated in the `context`
+    ///
+    /// # Safety
+    ///
+    /// The profile must be valid. `context` must be valid or null.
+    #[must_use]
+    pub unsafe fn new_thr(context: Context, profile: HPROFILE, intent: impl Into<IntentCode>) -> Option<Self> {
+        let intent = intent.into();
+        if cmsGetDeviceClass(profile) != ProfileClassSignature::NamedColorClass {
+            return None;
+        }
+        let lab = cmsCreateLab4ProfileTHR(context, ptr::null());
+        if lab.is_null() {
+            return None;
+        }
+        let to_lab = Transform::new_thr(context, profile, lab, intent, Default::default());
+        cmsCloseProfile(lab);
+        // Without an output profile, LCMS outputs the device colorants
+        let to_device = Transform::new_thr(context, profile, ptr::null_mut(), intent, Default::default());
+        Some(Self {
+            to_lab: to_lab.ok()?,
+            to_device: to_device.ok()?,
+        })
+    }
+
+    /// Number of colours
+    #[must_use]
+    pub fn len(&self) -> usize {
+        unsafe { cmsNamedColorCount(self.list()) as usize }
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Index of the colour with this name (`cmsNamedColorIndex`). Case-insensitive, and without the prefix or suffix.
+    #[must_use]
+    pub fn index_of(&self, name: &str) -> Option<NamedIndex> {
+        let name = CString::new(name).ok()?;
+        let index = unsafe { cmsNamedColorIndex(self.list(), name.as_ptr()) };
+        u16::try_from(index).ok().map(NamedIndex)
+    }
+
+    /// Colour with this name
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<NamedColor<D>> {
+        self.color(self.index_of(name)?)
+    }
+
+    /// Colour at this index
+    #[must_use]
+    pub fn color(&self, index: NamedIndex) -> Option<NamedColor<D>> {
+        let mut name = [0 as c_char; 256];
+        let mut prefix = [0 as c_char; 256];
+        let mut suffix = [0 as c_char; 256];
+        let found = unsafe {
+            cmsNamedColorInfo(self.list(), u32::from(index.0), name.as_mut_ptr(), prefix.as_mut_ptr(), suffix.as_mut_ptr(),
+                ptr::null_mut(), ptr::null_mut())
+        };
+        if found == 0 {
+            return None;
+        }
+        Some(NamedColor {
+            index,
+            name: to_string(&name),
+            prefix: to_string(&prefix),
+            suffix: to_string(&suffix),
+            lab: self.lab(index)?,
+            device: self.device(index)?,
+        })
+    }
+
+    /// All colours in order
+    pub fn iter(&self) -> impl Iterator<Item = NamedColor<D>> + '_ {
+        (0..self.len().min(usize::from(u16::MAX) + 1)).filter_map(move |i| self.color(NamedIndex(i as u16)))
+    }
+
+    /// PCS value of the colour
+    #[must_use]
+    pub fn lab(&self, index: NamedIndex) -> Option<CIELab> {
+        if usize::from(index.0) >= self.len() {
+            return None;
+        }
+        let mut out = [CIELab::default()];
+        self.to_lab.transform(&[index], &mut out);
+        Some(out[0])
+    }
+
+    /// Device colorants of the colour
+    #[must_use]
+    pub fn device(&self, index: NamedIndex) -> Option<D> {
+        if usize::from(index.0) >= self.len() {
+            return None;
+        }
+        // All zeros is a valid `Pixel`, and stays in channels LCMS doesn't write
+        let mut out = [unsafe { std::mem::zeroed::<D>() }];
+        self.to_device.transform(&[index], &mut out);
+        Some(out[0])
+    }
+
+    /// Transform to Lab, e.g. for whole images of indices
+    #[must_use]
+    pub fn to_lab(&self) -> &Transform<NamedIndex, CIELab> {
+        &self.to_lab
+    }
+
+    /// Transform to device colorants
+    #[must_use]
+    pub fn to_device(&self) -> &Transform<NamedIndex, D> {
+        &self.to_device
+    }
+
+    fn list(&self) -> *const NAMEDCOLORLIST {
+        unsafe { cmsGetNamedColorList(self.to_device.as_ptr()) }
+    }
+}
+
+fn to_string(s: &[c_char]) -> String {
+    unsafe { CStr::from_ptr(s.as_ptr()) }.to_string_lossy().into_owned()
+}
+
+#[test]
+fn spot_colors() {
+    use super::Cmyk;
+
+    unsafe {
+        let list = cmsAllocNamedColorList(ptr::null_mut(), 2, 4, b"PANTONE\0".as_ptr().cast(), b"C\0".as_ptr().cast());
+        for (name, lab, cmyk) in [(&b"185\0"[..], [47., 72., 43.], [0, 58000, 52000, 0]), (b"Process Blue\0", [43., -6., -50.], [65535, 6000, 0, 3000])] {
+            // namedColor2Type stores Lab in the ICC v2 encoding
+            let mut pcs = [0u16; 3];
+            cmsFloat2LabEncodedV2(pcs.as_mut_ptr(), &CIELab { L: lab[0], a: lab[1], b: lab[2] });
+            let mut colorants = [0u16; MAXCHANNELS];
+            colorants[..4].copy_from_slice(&cmyk);
+            assert_ne!(0, cmsAppendNamedColor(list, name.as_ptr().cast(), pcs.as_mut_ptr(), colorants.as_mut_ptr()));
+        }
+        let profile = cmsCreateProfilePlaceholder(ptr::null_mut());
+        cmsSetProfileVersion(profile, 4.3);
+        cmsSetDeviceClass(profile, ProfileClassSignature::NamedColorClass);
+        cmsSetColorSpace(profile, ColorSpaceSignature::CmykData);
+        cmsSetPCS(profile, ColorSpaceSignature::LabData);
+        assert_ne!(0, cmsWriteTag(profile, TagSignature::NamedColor2Tag, list.cast()));
+        cmsFreeNamedColorList(list);
+
+        assert!(NamedColors::<[u16; 3]>::new(profile, Intent::Perceptual).is_none());
+        let colors = NamedColors::<Cmyk<u16>>::new(profile, Intent::Perceptual).unwrap();
+        cmsCloseProfile(profile);
+
+        assert_eq!(2, colors.len());
+        assert_eq!(Some(NamedIndex(1)), colors.index_of("process blue"));
+        assert_eq!(None, colors.index_of("186"));
+        let red = colors.get("185").unwrap();
+        assert_eq!("PANTONE 185 C", red.full_name());
+        assert_eq!(Cmyk([0, 58000, 52000, 0]), red.device);
+        assert!((red.lab.L - 47.).abs() < 0.1 && (red.lab.a - 72.).abs() < 0.1, "{:?}", red.lab);
+        assert_eq!(None, colors.lab(NamedIndex(2)));
+        let names: Vec<_> = colors.iter().map(|c| c.name).collect();
+        assert_eq!(["185", "Process Blue"], &names[..]);
+
+        let mut labs = [CIELab::default(); 2];
+        colors.to_lab().transform(&[NamedIndex(1), NamedIndex(0)], &mut labs);
+        assert!((labs[0].b + 50.).abs() < 0.1, "{labs:?}");
+    }
+}
@@ -0,0 +1,410 @@
+//! Soft-proofing and gamut-check transforms (`cmsCreateProofingTransform`), configured by name rather than position.
+
+use super::{Cmyk, Flags, Pixel, Transform, TransformError};
+use crate::ffi::*;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::ptr::{self, NonNull};
+
+/// Output pixels usable as the gamut alarm colour
+pub trait AlarmColor {
+    /// Values in LCMS alarm code scale, 0..=65535 per channel
+    fn alarm_codes(&self) -> [u16; MAXCHANNELS];
+}
+
+/// Channel types of [`AlarmColor`] pixels
+pub trait AlarmChannel: Copy {
+    fn to_u16(self) -> u16;
+
+    /// Float CMYK is in 0..=100 range
+    fn cmyk_to_u16(self) -> u16 {
+        self.to_u16()
+    }
+}
+
+impl AlarmChannel for u8 {
+    fn to_u16(self) -> u16 {
+        u16::from(self) * 257
+    }
+}
+
+impl AlarmChannel for u16 {
+    fn to_u16(self) -> u16 {
+        self
+    }
+}
+
+impl AlarmChannel for f32 {
+    fn to_u16(self) -> u16 {
+        (self.clamp(0., 1.) * 65535.).round() as u16
+    }
+
+    fn cmyk_to_u16(self) -> u16 {
+        (self / 100.).to_u16()
+    }
+}
+
+impl AlarmChannel for f64 {
+    fn to_u16(self) -> u16 {
+        (self.clamp(0., 1.) * 65535.).round() as u16
+    }
+
+    fn cmyk_to_u16(self) -> u16 {
+        (self / 100.).to_u16()
+    }
+}
+
+impl<T: AlarmChannel, const N: usize> AlarmColor for [T; N] {
+    fn alarm_codes(&self) -> [u16; MAXCHANNELS] {
+        let mut codes = [0; MAXCHANNELS];
+        for (code, &v) in codes.iter_mut().zip(self) {
+            *code = v.to_u16();
+        }
+        codes
+    }
+}
+
+impl<T: AlarmChannel> AlarmColor for Cmyk<T> {
+    fn alarm_codes(&self) -> [u16; MAXCHANNELS] {
+        let mut codes = [0; MAXCHANNELS];
+        for (code, &v) in codes.iter_mut().zip(&self.0) {
+            *code = v.cmyk_to_u16();
+        }
+        codes
+    }
+}
+
+/// Creates a [`ProofingTransform`] that simulates the `proofing` profile's device on the output device
+pub struct ProofingBuilder<In, Out> {
+    context: Context,
+    proofing: HPROFILE,
+    intent: IntentCode,
+    proofing_intent: IntentCode,
+    soft_proofing: bool,
+    gamut_check: bool,
+    alarm: Option<[u16; MAXCHANNELS]>,
+    gamut_threshold: Option<f64>,
+    black_point_compensation: bool,
+    adaptation_state: Option<f64>,
+    flags: Flags,
+    _pixels: PhantomData<fn(&In) -> Out>,
+}
+
+impl<In: Pixel, Out: Pixel> ProofingBuilder<In, Out> {
+    /// Soft proofing with perceptual intent, absolute colorimetric proofing intent, and no gamut check
+    #[must_use]
+    pub fn new(proofing: HPROFILE) -> Self {
+        Self {
+            context: ptr::null_mut(),
+            proofing,
+            intent: Intent::Perceptual.into(),
+            proofing_intent: Intent::AbsoluteColorimetric.into(),
+            soft_proofing: true,
+            gamut_check: false,
+            alarm: None,
+            gamut_threshold: None,
+            black_point_compensation: false,
+            adaptation_state: None,
+            flags: Flags::new(),
+            _pixels: PhantomData,
+        }
+    }
+
+    /// Context to allocate the transform in. It must stay valid until [`ProofingBuilder::build`].
+    #[must_use]
+    pub fn context(mut self, context: Context) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Intent from the input to the proofing device
+    #[must_use]
+    pub fn intent(mut self, intent: impl Into<IntentCode>) -> Self {
+        self.intent = intent.into();
+        self
+    }
+
+    /// Intent from the proofing device to the output
+    #[must_use]
+    pub fn proofing_intent(mut self, intent: impl Into<IntentCode>) -> Self {
+        self.proofing_intent = intent.into();
+        self
+    }
+
+    /// `FLAGS_SOFTPROOFING`. Without it and without the gamut check, it's a plain transform from the input to the output.
+    ///
+    /// Like in LCMS, the gamut check always goes through the proofing device, so it's simulated even without this flag.
+    #[must_use]
+    pub fn soft_proofing(mut self, enable: bool) -> Self {
+        self.soft_proofing = enable;
+        self
+    }
+
+    /// `FLAGS_GAMUTCHECK`. Colours out of the proofing gamut become the alarm colour.
+    #[must_use]
+    pub fn gamut_check(mut self, enable: bool) -> Self {
+        self.gamut_check = enable;
+        self
+    }
+
+    /// Enables the gamut check with this colour for out-of-gamut pixels.
+    ///
+    /// LCMS keeps alarm codes per context, so the transform gets its own copy of the context.
+    #[must_use]
+    pub fn gamut_alarm(mut self, color: Out) -> Self where Out: AlarmColor {
+        self.gamut_check = true;
+        self.alarm = Some(color.alarm_codes());
+        self
+    }
+
+    /// ΔE threshold of [`ProofingTransform::gamut_mask`].
+    ///
+    /// By default it's the same as in LCMS's gamut check: 1 for matrix-shaper proofing profiles, and 5 for others.
+    #[must_use]
+    pub fn gamut_threshold(mut self, delta_e: f64) -> Self {
+        self.gamut_threshold = Some(delta_e);
+        self
+    }
+
+    /// Black point compensation from the input to the proofing device
+    #[must_use]
+    pub fn black_point_compensation(mut self, enable: bool) -> Self {
+        self.black_point_compensation = enable;
+        self
+    }
+
+    /// Observer adaptation state for absolute colorimetric intents, 0..=1. The context's state by default.
+    #[must_use]
+    pub fn adaptation_state(mut self, state: f64) -> Self {
+        self.adaptation_state = Some(state);
+        self
+    }
+
+    /// Other flags for the transform. Proofing, gamut check and BPC flags are set by the other methods.
+    #[must_use]
+    pub fn flags(mut self, flags: Flags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Creates the transform like `cmsCreateProofingTransformTHR` does.
+    ///
+    /// Fails if the flags contradict each other, or LCMS can't link the profiles.
+    ///
+    /// # Safety
+    ///
+    /// Profiles and the context must be valid. Profiles can be closed once the transform is created.
+    pub unsafe fn build(self, input: HPROFILE, output: HPROFILE) -> Result<ProofingTransform<In, Out>, TransformError> {
+        let owned_context = match self.alarm {
+            Some(codes) => {
+                let context = OwnedContext(NonNull::new(cmsDupContext(self.context, ptr::null_mut())).ok_or(TransformError::Failed)?);
+                cmsSetAlarmCodesTHR(context.0.as_ptr(), codes.as_ptr());
+                Some(context)
+            },
+            None => None,
+        };
+        let context = owned_context.as_ref().map_or(self.context, |c| c.0.as_ptr());
+        let adaptation = self.adaptation_state.unwrap_or_else(|| cmsSetAdaptationStateTHR(context, -1.));
+        let bpc = Bool::from(self.black_point_compensation);
+
+        let mut flags = self.flags.without(Flags::SOFT_PROOFING | Flags::GAMUT_CHECK | Flags::BLACK_POINT_COMPENSATION);
+        if self.soft_proofing {
+            flags |= Flags::SOFT_PROOFING;
+        }
+        if self.gamut_check {
+            flags |= Flags::GAMUT_CHECK;
+        }
+        if self.black_point_compensation {
+            flags |= Flags::BLACK_POINT_COMPENSATION;
+        }
+        flags.validate().map_err(TransformError::Flags)?;
+        let flags = flags.bits();
+        let gamut = if self.gamut_check { self.proofing } else { ptr::null_mut() };
+        // Same chains as cmsCreateProofingTransformTHR. Gamut check goes through the proofing device even without soft proofing.
+        let xform = if self.soft_proofing || self.gamut_check {
+            let mut profiles = [input, self.proofing, self.proofing, output];
+            let mut intents = [self.intent.0, self.intent.0, Intent::RelativeColorimetric as u32, self.proofing_intent.0];
+            let mut bpcs = [bpc, bpc, 0, 0];
+            let mut adaptations = [adaptation; 4];
+            cmsCreateExtendedTransform(context, 4, profiles.as_mut_ptr(), bpcs.as_mut_ptr(), intents.as_mut_ptr(), adaptations.as_mut_ptr(),
+                gamut, 1, In::FORMAT, Out::FORMAT, flags)
+        } else {
+            crate::plugin::intents::create_transform(context, input, In::FORMAT, output, Out::FORMAT, self.intent, flags)
+        };
+        let transform = Transform::from_ptr(xform).ok_or(TransformError::Failed)?;
+
+        // Same round trips through the proofing device as LCMS's gamut check
+        let lab = cmsCreateLab4ProfileTHR(context, ptr::null());
+        if lab.is_null() {
+            return Err(TransformError::Failed);
+        }
+        let mut profiles = [input, lab];
+        let mut intents = [self.intent.0, Intent::RelativeColorimetric as u32];
+        let mut bpcs = [bpc, 0];
+        let mut adaptations = [adaptation, 1.];
+        let to_lab = Transform::from_ptr(cmsCreateExtendedTransform(context, 2, profiles.as_mut_ptr(), bpcs.as_mut_ptr(), intents.as_mut_ptr(),
+            adaptations.as_mut_ptr(), ptr::null_mut(), 0, In::FORMAT, PixelFormat::Lab_DBL, FLAGS_NOCACHE));
+        let space = cmsGetColorSpace(self.proofing);
+        let channels = cmsChannelsOf(space);
+        let device = PixelFormat(COLORSPACE_SH(PixelType(_cmsLCMScolorSpace(space) as u32)) | CHANNELS_SH(channels) | BYTES_SH(2));
+        let round_trip = RoundTrip {
+            forward: cmsCreateTransformTHR(context, lab, PixelFormat::Lab_DBL, self.proofing, device, Intent::RelativeColorimetric, FLAGS_NOCACHE),
+            reverse: cmsCreateTransformTHR(context, self.proofing, device, lab, PixelFormat::Lab_DBL, Intent::RelativeColorimetric, FLAGS_NOCACHE),
+            channels: channels as usize,
+        };
+        cmsCloseProfile(lab);
+        if round_trip.forward.is_null() || round_trip.reverse.is_null() {
+            return Err(TransformError::Failed);
+        }
+        let threshold = if cmsIsMatrixShaper(self.proofing) != 0 { 1. } else { 5. };
+
+        Ok(ProofingTransform {
+            transform,
+            to_lab: to_lab.ok_or(TransformError::Failed)?,
+            round_trip,
+            gamut_threshold: self.gamut_threshold.unwrap_or(threshold),
+            _context: owned_context,
+        })
+    }
+}
+
+/// Transform from [`ProofingBuilder`]. It derefs to the [`Transform`].
+pub struct ProofingTransform<In, Out> {
+    transform: Transform<In, Out>,
+    to_lab: Transform<In, CIELab>,
+    round_trip: RoundTrip,
+    gamut_threshold: f64,
+    // Dropped after the transforms that use it
+    _context: Option<OwnedContext>,
+}
+
+impl<In: Pixel, Out: Pixel> ProofingTransform<In, Out> {
+    /// Sets `mask` to 255 where `input` is out of the proofing device's gamut, and to 0 elsewhere.
+    ///
+    /// Pixels are classified the same way as LCMS's gamut check does, but exactly rather than from a precalculated table.
+    ///
+    /// # Panics
+    ///
+    /// If the slices have different lengths.
+    pub fn gamut_mask(&self, input: &[In], mask: &mut [u8]) {
+        assert_eq!(input.len(), mask.len(), "input and mask must have the same number of pixels");
+        let mut lab = vec![CIELab::default(); input.len()];
+        self.to_lab.transform(input, &mut lab);
+        let once = self.round_trip.run(&lab);
+        let twice = self.round_trip.run(&once);
+        let t = self.gamut_threshold;
+        for (m, ((lab, once), twice)) in mask.iter_mut().zip(lab.iter().zip(&once).zip(&twice)) {
+            let (de1, de2) = unsafe { (cmsDeltaE(lab, once), cmsDeltaE(once, twice)) };
+            // A big error of the second round trip too could be due to perceptual mapping
+            let out = de1 > t && (de2 < t || de1 / de2 > t);
+            *m = if out { 255 } else { 0 };
+        }
+    }
+
+    /// [`Transform::transform`] and [`ProofingTransform::gamut_mask`] together
+    pub fn transform_with_mask(&self, input: &[In], output: &mut [Out], mask: &mut [u8]) {
+        self.transform.transform(input, output);
+        self.gamut_mask(input, mask);
+    }
+}
+
+impl<In, Out> Deref for ProofingTransform<In, Out> {
+    type Target = Transform<In, Out>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.transform
+    }
+}
+
+/// Lab to the proofing device in 16 bits and back
+struct RoundTrip {
+    forward: HTRANSFORM,
+    reverse: HTRANSFORM,
+    channels: usize,
+}
+
+unsafe impl Send for RoundTrip {}
+unsafe impl Sync for RoundTrip {}
+
+impl RoundTrip {
+    fn run(&self, lab: &[CIELab]) -> Vec<CIELab> {
+        let mut device = vec![0u16; lab.len() * self.channels];
+        let mut out = vec![CIELab::default(); lab.len()];
+        for (lab, (device, out)) in lab.chunks(u32::MAX as usize).zip(device.chunks_mut(u32::MAX as usize * self.channels).zip(out.chunks_mut(u32::MAX as usize))) {
+            unsafe {
+                cmsDoTransform(self.forward, lab.as_ptr().cast(), device.as_mut_ptr().cast(), lab.len() as u32);
+                cmsDoTransform(self.reverse, device.as_ptr().cast(), out.as_mut_ptr().cast(), lab.len() as u32);
+            }
+        }
+        out
+    }
+}
+
+impl Drop for RoundTrip {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.forward.is_null() {
+                cmsDeleteTransform(self.forward);
+            }
+            if !self.reverse.is_null() {
+                cmsDeleteTransform(self.reverse);
+            }
+        }
+    }
+}
+
+struct OwnedContext(NonNull<_cmsContext_struct>);
+
+// Only used to keep alarm codes, which LCMS never changes after creation
+unsafe impl Send for OwnedContext {}
+unsafe impl Sync for OwnedContext {}
+
+impl Drop for OwnedContext {
+    fn drop(&mut self) {
+        unsafe { cmsDeleteContext(self.0.as_ptr()) }
+    }
+}
+
+#[test]
+fn soft_proof_with_alarm() {
+    unsafe {
+        let srgb = cmsCreate_sRGBProfile();
+        // Proofing device with desaturated primaries
+        let gamma = cmsBuildGamma(ptr::null_mut(), 2.2);
+        let primaries = CIExyYTRIPLE {
+            Red: CIExyY { x: 0.45, y: 0.33, Y: 1. },
+            Green: CIExyY { x: 0.3, y: 0.45, Y: 1. },
+            Blue: CIExyY { x: 0.25, y: 0.25, Y: 1. },
+        };
+        let narrow = cmsCreateRGBProfile(&CIExyY { x: 0.3127, y: 0.329, Y: 1. }, &primaries, [gamma as *const ToneCurve; 3].as_ptr());
+        cmsFreeToneCurve(gamma);
+        let proof = ProofingBuilder::<[u8; 3], [u8; 3]>::new(narrow)
+            .proofing_intent(Intent::RelativeColorimetric)
+            .gamut_alarm([255, 0, 255])
+            .adaptation_state(1.)
+            .build(srgb, srgb)
+            .unwrap();
+        let alarm_only = ProofingBuilder::<[u8; 3], [u8; 3]>::new(narrow)
+            .soft_proofing(false)
+            .gamut_alarm([0, 255, 0])
+            .build(srgb, srgb)
+            .unwrap();
+        cmsCloseProfile(narrow);
+        cmsCloseProfile(srgb);
+
+        let input = [[128u8, 128, 128], [0, 0, 255]];
+        let mut output = [[0u8; 3]; 2];
+        let mut mask = [1u8; 2];
+        proof.transform_with_mask(&input, &mut output, &mut mask);
+        assert_eq!([0, 255], mask);
+        assert!(output[0].iter().all(|v| v.abs_diff(128) < 3), "{output:?}");
+        assert_eq!([255, 0, 255], output[1]);
+        alarm_only.transform(&input, &mut output);
+        assert!(output[0].iter().all(|v| v.abs_diff(128) < 2), "{output:?}");
+        assert_eq!([0, 255, 0], output[1]);
+        // The global alarm codes are unchanged
+        let mut codes = [0u16; MAXCHANNELS];
+        cmsGetAlarmCodes(codes.as_mut_ptr());
+        assert_ne!(65535, codes[0]);
+    }
+}